@@ -11,6 +11,7 @@ fn get_buffer() {
         min_address: 0_usize,
         max_address: i32::MAX as usize,
         size: 4096,
+        ..BufferSearchSettings::new()
     };
 
     // Automatically dropped.
@@ -0,0 +1,359 @@
+use crate::backends::MemoryBackend;
+use crate::structs::MemoryPermissions;
+use crate::utilities::cached::get_sys_info;
+use crate::utilities::linux_huge_pages::get_huge_page_size;
+use crate::utilities::linux_map_parser::get_free_regions_from_process_id;
+use crate::utilities::map_parser_utilities::MemoryMapEntry;
+use core::cell::Cell;
+use core::ops::Deref;
+use core::ptr::write_volatile;
+use errno::errno;
+use libc::{
+    madvise, mmap, mprotect, munmap, EEXIST, MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED,
+    MAP_FIXED_NOREPLACE, MAP_HUGETLB, MAP_PRIVATE, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE,
+};
+
+#[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+use crate::utilities::{
+    linux_process_memory::write_process_memory, linux_remote_process::RemoteProcess,
+};
+
+// Abstractions for memory management syscalls //
+trait LinuxMemory {
+    /// Maps read/write/execute memory, returning the mapped address or the `errno`.
+    unsafe fn mmap(&self, addr: usize, size: usize, flags: i32) -> Result<usize, i32>;
+    unsafe fn munmap(&self, addr: usize, size: usize) -> Result<(), i32>;
+    unsafe fn mprotect(&self, addr: usize, size: usize, prot: i32) -> Result<(), i32>;
+    unsafe fn madvise(&self, addr: usize, size: usize, advice: i32);
+
+    /// Writes to the first byte of a page, so it gets backed by physical memory.
+    unsafe fn touch(&self, addr: usize);
+}
+
+struct LocalMemory;
+
+impl LinuxMemory for LocalMemory {
+    unsafe fn mmap(&self, addr: usize, size: usize, flags: i32) -> Result<usize, i32> {
+        let allocated = mmap(
+            addr as *mut _,
+            size,
+            PROT_READ | PROT_WRITE | PROT_EXEC,
+            flags,
+            -1,
+            0,
+        );
+
+        if allocated == MAP_FAILED {
+            return Err(errno().0);
+        }
+
+        Ok(allocated as usize)
+    }
+
+    unsafe fn munmap(&self, addr: usize, size: usize) -> Result<(), i32> {
+        if munmap(addr as *mut _, size) != 0 {
+            return Err(errno().0);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn mprotect(&self, addr: usize, size: usize, prot: i32) -> Result<(), i32> {
+        if mprotect(addr as *mut _, size, prot) != 0 {
+            return Err(errno().0);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn madvise(&self, addr: usize, size: usize, advice: i32) {
+        madvise(addr as *mut _, size, advice);
+    }
+
+    unsafe fn touch(&self, addr: usize) {
+        write_volatile(addr as *mut u8, 0);
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+struct RemoteMemory {
+    process: RemoteProcess,
+    pid: i32,
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+impl LinuxMemory for RemoteMemory {
+    unsafe fn mmap(&self, addr: usize, size: usize, flags: i32) -> Result<usize, i32> {
+        self.process
+            .mmap(addr, size, PROT_READ | PROT_WRITE | PROT_EXEC, flags)
+    }
+
+    unsafe fn munmap(&self, addr: usize, size: usize) -> Result<(), i32> {
+        self.process.munmap(addr, size).map(|_| ())
+    }
+
+    unsafe fn mprotect(&self, addr: usize, size: usize, prot: i32) -> Result<(), i32> {
+        self.process.mprotect(addr, size, prot).map(|_| ())
+    }
+
+    unsafe fn madvise(&self, addr: usize, size: usize, advice: i32) {
+        let _ = self.process.madvise(addr, size, advice);
+    }
+
+    unsafe fn touch(&self, addr: usize) {
+        let _ = write_process_memory(self.pid, addr, &[0]);
+    }
+}
+
+/// The process memory is mapped in; derefs to its [`LinuxMemory`] implementation.
+// Not boxed, so the backend works without the `alloc` feature; there's one per allocation.
+#[allow(clippy::large_enum_variant)]
+enum Memory {
+    Local(LocalMemory),
+
+    #[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+    Remote(RemoteMemory),
+}
+
+impl Deref for Memory {
+    type Target = dyn LinuxMemory;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Memory::Local(memory) => memory,
+
+            #[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+            Memory::Remote(memory) => memory,
+        }
+    }
+}
+
+/// How huge pages are obtained for an allocation.
+#[derive(Clone, Copy, PartialEq)]
+enum HugePages {
+    /// Huge pages were not requested.
+    None,
+
+    /// Explicit huge pages, from the pool reserved by the system administrator.
+    Explicit,
+
+    /// Transparent huge pages, i.e. regular pages which the kernel is asked to back with huge pages.
+    Transparent,
+}
+
+/// Maps memory with `mmap`, reading free regions from `/proc/<pid>/maps`.
+///
+/// This is the backend used by the allocator on Linux.
+pub struct LinuxBackend {
+    memory: Memory,
+    process_id: u32,
+    granularity: usize,
+
+    /// Falls back from explicit to transparent huge pages once the former run out.
+    huge_pages: Cell<HugePages>,
+}
+
+impl LinuxBackend {
+    /// Creates a backend for the current process.
+    pub fn new() -> Self {
+        Self {
+            memory: Memory::Local(LocalMemory),
+            process_id: get_sys_info().this_process_id,
+            granularity: get_sys_info().page_size as usize,
+            huge_pages: Cell::new(HugePages::None),
+        }
+    }
+
+    /// Creates a backend for another process, attaching to it with `ptrace`.
+    ///
+    /// # Arguments
+    ///
+    /// * `process_id` - ID of the process to map memory in.
+    ///
+    /// # Returns
+    ///
+    /// The backend, or the `errno` if the process can't be attached to.
+    /// The process stays stopped until the backend is dropped.
+    #[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+    pub fn for_process(process_id: u32) -> Result<Self, i32> {
+        if process_id == get_sys_info().this_process_id {
+            return Ok(Self::new());
+        }
+
+        let pid = process_id as i32;
+        Ok(Self {
+            memory: Memory::Remote(RemoteMemory {
+                process: RemoteProcess::attach(pid)?,
+                pid,
+            }),
+            process_id,
+            ..Self::new()
+        })
+    }
+
+    /// Backs mapped memory with huge pages, if the system supports them.
+    /// See [`BufferAllocatorSettings::huge_pages`].
+    ///
+    /// [`BufferAllocatorSettings::huge_pages`]: crate::structs::params::BufferAllocatorSettings::huge_pages
+    pub fn with_huge_pages(mut self) -> Self {
+        if let Some(huge_page_size) = get_huge_page_size() {
+            self.granularity = huge_page_size;
+            self.huge_pages.set(HugePages::Explicit);
+        }
+
+        self
+    }
+
+    /// Returns the ID of the process memory is mapped in.
+    pub fn process_id(&self) -> u32 {
+        self.process_id
+    }
+
+    /// Asks the kernel for memory in the low 2GiB with `MAP_32BIT`.
+    ///
+    /// # Returns
+    ///
+    /// The mapped address, or `None` on failure.
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn map_32bit(&self, size: usize) -> Option<usize> {
+        unsafe {
+            self.memory
+                .mmap(0, size, MAP_PRIVATE | MAP_ANONYMOUS | libc::MAP_32BIT)
+                .ok()
+        }
+    }
+
+    /// Maps read/write/execute memory at exactly `addr`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if mapped, `false` if memory was mapped elsewhere (on kernels that ignore
+    /// `MAP_FIXED_NOREPLACE`), or the `errno` on failure.
+    unsafe fn map_at(&self, addr: usize, size: usize, extra_flags: i32) -> Result<bool, i32> {
+        let allocated = self.memory.mmap(
+            addr,
+            size,
+            MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE | extra_flags,
+        )?;
+
+        // Error handling for older kernels before 2018 that don't respect MAP_FIXED_NOREPLACE.
+        if allocated != addr {
+            let _ = self.memory.munmap(allocated, size);
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+}
+
+impl Default for LinuxBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBackend for LinuxBackend {
+    fn allocation_granularity(&self) -> usize {
+        self.granularity
+    }
+
+    fn free_regions(&self, on_region: &mut dyn FnMut(MemoryMapEntry)) -> Result<(), i32> {
+        get_free_regions_from_process_id(self.process_id as i32, on_region)
+    }
+
+    fn map(&self, address: usize, size: usize) -> Result<bool, i32> {
+        unsafe {
+            if self.huge_pages.get() == HugePages::Explicit {
+                match self.map_at(address, size, MAP_HUGETLB) {
+                    Ok(mapped) => return Ok(mapped),
+                    Err(EEXIST) => return Ok(false),
+                    Err(_) => {
+                        // No (free) explicit huge pages, fall back to transparent ones from now on.
+                        self.huge_pages.set(HugePages::Transparent);
+                    }
+                }
+            }
+
+            // EEXIST: Something was mapped here since the memory map was read.
+            match self.map_at(address, size, 0) {
+                Ok(true) => {}
+                Ok(false) | Err(EEXIST) => return Ok(false),
+                Err(error) => return Err(error),
+            }
+
+            if self.huge_pages.get() == HugePages::Transparent {
+                // Kernel may ignore this (e.g. transparent huge pages are disabled), which is fine.
+                self.memory.madvise(address, size, MADV_HUGEPAGE);
+
+                // Touch the memory so the page size can be reported right away.
+                self.memory.touch(address);
+            }
+
+            Ok(true)
+        }
+    }
+
+    unsafe fn unmap(&self, address: usize, size: usize) -> Result<(), i32> {
+        self.memory.munmap(address, size)
+    }
+
+    unsafe fn protect(
+        &self,
+        address: usize,
+        size: usize,
+        permissions: MemoryPermissions,
+    ) -> Result<(), i32> {
+        let mut prot = PROT_NONE;
+        if permissions.read {
+            prot |= PROT_READ;
+        }
+        if permissions.write {
+            prot |= PROT_WRITE;
+        }
+        if permissions.execute {
+            prot |= PROT_EXEC;
+        }
+
+        self.memory.mprotect(address, size, prot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::MemoryMap;
+
+    #[test]
+    fn can_map_protect_and_unmap() {
+        let backend = LinuxBackend::new();
+        let size = backend.allocation_granularity();
+        let mut regions = Vec::new();
+        backend.free_regions(&mut |x| regions.push(x)).unwrap();
+        let region = regions
+            .iter()
+            .find(|x| x.start_address >= 0x1000_0000 && x.end_address - x.start_address > size)
+            .unwrap();
+
+        let address = region.start_address;
+        assert_eq!(backend.map(address, size), Ok(true));
+        assert_eq!(backend.map(address, size), Ok(false));
+
+        unsafe {
+            let read_only = MemoryPermissions {
+                read: true,
+                ..MemoryPermissions::default()
+            };
+            backend.protect(address, size, read_only).unwrap();
+            let permissions = MemoryMap::from_current_process()
+                .unwrap()
+                .protection_at(address)
+                .unwrap();
+            assert!(permissions.read && !permissions.write && !permissions.execute);
+
+            backend.unmap(address, size).unwrap();
+        }
+
+        let map = MemoryMap::from_current_process().unwrap();
+        assert_eq!(map.protection_at(address), None);
+    }
+}
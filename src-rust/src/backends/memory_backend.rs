@@ -0,0 +1,73 @@
+use crate::structs::MemoryPermissions;
+use crate::utilities::map_parser_utilities::MemoryMapEntry;
+
+/// Operating system functionality used by the buffer allocator.
+///
+/// The allocator only finds and maps memory through this trait; so the allocation algorithm can be
+/// run against any address space, e.g. a [`SimulatedBackend`] which reproduces a fragmented layout
+/// from a bug report. See [`Buffers::allocate_with_backend`].
+///
+/// Error codes are OS specific, they are reported back to the caller as-is.
+///
+/// [`SimulatedBackend`]: crate::backends::SimulatedBackend
+/// [`Buffers::allocate_with_backend`]: crate::buffers::Buffers::allocate_with_backend
+pub trait MemoryBackend {
+    /// Returns the granularity of allocations; mapped addresses and sizes are multiples of this.
+    fn allocation_granularity(&self) -> usize;
+
+    /// Reads the free regions of the address space.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_region` - Called with each free region, in order of address; where `end_address` is the
+    ///   last free byte (inclusive).
+    ///
+    /// # Returns
+    ///
+    /// The OS error code if the address space can't be read.
+    ///
+    /// # Remarks
+    ///
+    /// Regions are passed to a callback rather than returned, so they can be read without allocating.
+    fn free_regions(&self, on_region: &mut dyn FnMut(MemoryMapEntry)) -> Result<(), i32>;
+
+    /// Maps read/write/execute memory at exactly the given address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address to map memory at, aligned to [`MemoryBackend::allocation_granularity`].
+    /// * `size` - Number of bytes to map.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the memory was mapped, `false` if the address is already in use; or the OS error
+    /// code if mapping failed for any other reason.
+    fn map(&self, address: usize, size: usize) -> Result<bool, i32>;
+
+    /// Unmaps memory.
+    ///
+    /// # Returns
+    ///
+    /// The OS error code on failure.
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be in use.
+    unsafe fn unmap(&self, address: usize, size: usize) -> Result<(), i32>;
+
+    /// Changes the permissions of mapped memory. [`MemoryPermissions::shared`] is ignored.
+    ///
+    /// # Returns
+    ///
+    /// The OS error code on failure.
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be accessed in a way the new permissions don't allow.
+    unsafe fn protect(
+        &self,
+        address: usize,
+        size: usize,
+        permissions: MemoryPermissions,
+    ) -> Result<(), i32>;
+}
@@ -0,0 +1,323 @@
+extern crate alloc;
+use crate::backends::MemoryBackend;
+use crate::structs::MemoryPermissions;
+use crate::utilities::map_parser_utilities::MemoryMapEntry;
+use core::cell::RefCell;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// `EINVAL` on Linux, returned for unaligned or empty requests.
+const INVALID_ARGUMENT: i32 = 22;
+
+/// `ENOMEM` on Linux, returned for requests outside the address space or touching unmapped memory.
+const OUT_OF_MEMORY: i32 = 12;
+
+/// A mapping in a [`SimulatedBackend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulatedMapping {
+    /// Address of the first byte of the mapping.
+    pub start_address: usize,
+
+    /// Address of the byte after the last byte of the mapping.
+    pub end_address: usize,
+
+    pub permissions: MemoryPermissions,
+}
+
+/// An address space which exists only in memory, for testing allocations deterministically.
+///
+/// Nothing is ever actually mapped; mapping only updates the simulated layout. Error codes follow
+/// Linux conventions.
+///
+/// # Example
+///
+/// ```
+/// use reloaded_memory_buffers::backends::SimulatedBackend;
+/// use reloaded_memory_buffers::buffers::Buffers;
+/// use reloaded_memory_buffers::structs::params::BufferAllocatorSettings;
+///
+/// // Only 0x20000..0x40000 is free below 0x50000.
+/// let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x10000);
+/// backend.add_mapping(0, 0x20000);
+/// backend.add_mapping(0x40000, 0x50000);
+///
+/// let mut settings = BufferAllocatorSettings::new();
+/// settings.max_address = 0x50000;
+/// settings.size = 0x10000;
+///
+/// let item = Buffers::allocate_with_backend(&mut settings, &backend).unwrap();
+/// let base_address = item.base_address.value;
+/// assert_eq!(base_address, 0x20000);
+/// ```
+pub struct SimulatedBackend {
+    max_address: usize,
+    allocation_granularity: usize,
+    mappings: RefCell<Vec<SimulatedMapping>>,
+
+    /// Ranges mapping fails in (start, end, error code).
+    refused: RefCell<Vec<(usize, usize, i32)>>,
+}
+
+impl SimulatedBackend {
+    /// Creates an empty address space.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_address` - Highest address in the address space (inclusive).
+    /// * `allocation_granularity` - Granularity of allocations, a power of 2.
+    pub fn new(max_address: usize, allocation_granularity: usize) -> Self {
+        Self {
+            max_address,
+            allocation_granularity,
+            mappings: RefCell::new(Vec::new()),
+            refused: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Marks a range as mapped, e.g. a module or a heap from a memory map in a bug report.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_address` - Address of the first byte of the range.
+    /// * `end_address` - Address of the byte after the last byte of the range.
+    pub fn add_mapping(&self, start_address: usize, end_address: usize) {
+        self.insert(SimulatedMapping {
+            start_address,
+            end_address,
+            permissions: MemoryPermissions {
+                read: true,
+                ..MemoryPermissions::default()
+            },
+        });
+    }
+
+    /// Makes mapping memory which overlaps the given range fail, while it still appears free.
+    /// Simulates memory the OS won't hand out, e.g. below `vm.mmap_min_addr` on Linux.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_address` - Address of the first byte of the range.
+    /// * `end_address` - Address of the byte after the last byte of the range.
+    /// * `os_error` - Error code [`MemoryBackend::map`] fails with.
+    pub fn refuse_mapping(&self, start_address: usize, end_address: usize, os_error: i32) {
+        self.refused
+            .borrow_mut()
+            .push((start_address, end_address, os_error));
+    }
+
+    /// Returns all mappings, sorted by address.
+    pub fn mappings(&self) -> Vec<SimulatedMapping> {
+        self.mappings.borrow().clone()
+    }
+
+    /// Returns the permissions of the memory at the given address, or `None` if it is not mapped.
+    pub fn protection_at(&self, address: usize) -> Option<MemoryPermissions> {
+        self.mappings
+            .borrow()
+            .iter()
+            .find(|x| address >= x.start_address && address < x.end_address)
+            .map(|x| x.permissions)
+    }
+
+    fn insert(&self, mapping: SimulatedMapping) {
+        let mut mappings = self.mappings.borrow_mut();
+        let index = mappings.partition_point(|x| x.start_address < mapping.start_address);
+        mappings.insert(index, mapping);
+    }
+
+    fn is_mapped(&self, start_address: usize, end_address: usize) -> bool {
+        self.mappings
+            .borrow()
+            .iter()
+            .any(|x| x.start_address < end_address && start_address < x.end_address)
+    }
+
+    fn validate(&self, address: usize, size: usize) -> Result<usize, i32> {
+        if size == 0 || !address.is_multiple_of(self.allocation_granularity) {
+            return Err(INVALID_ARGUMENT);
+        }
+
+        match address.checked_add(size) {
+            Some(end) if end - 1 <= self.max_address => Ok(end),
+            _ => Err(OUT_OF_MEMORY),
+        }
+    }
+
+    /// Splits the mappings at the given addresses, so no mapping crosses either.
+    fn split(&self, start_address: usize, end_address: usize) {
+        let mut mappings = self.mappings.borrow_mut();
+        for boundary in [start_address, end_address] {
+            if let Some(index) = mappings
+                .iter()
+                .position(|x| boundary > x.start_address && boundary < x.end_address)
+            {
+                let mut upper = mappings[index];
+                upper.start_address = boundary;
+                mappings[index].end_address = boundary;
+                mappings.insert(index + 1, upper);
+            }
+        }
+    }
+}
+
+impl MemoryBackend for SimulatedBackend {
+    fn allocation_granularity(&self) -> usize {
+        self.allocation_granularity
+    }
+
+    fn free_regions(&self, on_region: &mut dyn FnMut(MemoryMapEntry)) -> Result<(), i32> {
+        let mut last_end_address = 0;
+        for mapping in self.mappings.borrow().iter() {
+            if mapping.start_address > last_end_address {
+                on_region(MemoryMapEntry::new(
+                    last_end_address,
+                    mapping.start_address - 1,
+                ));
+            }
+
+            last_end_address = last_end_address.max(mapping.end_address);
+        }
+
+        if last_end_address < self.max_address {
+            on_region(MemoryMapEntry::new(last_end_address, self.max_address));
+        }
+
+        Ok(())
+    }
+
+    fn map(&self, address: usize, size: usize) -> Result<bool, i32> {
+        let end_address = self.validate(address, size)?;
+        if let Some(refused) = self
+            .refused
+            .borrow()
+            .iter()
+            .find(|x| x.0 < end_address && address < x.1)
+        {
+            return Err(refused.2);
+        }
+
+        if self.is_mapped(address, end_address) {
+            return Ok(false);
+        }
+
+        self.insert(SimulatedMapping {
+            start_address: address,
+            end_address,
+            permissions: MemoryPermissions::READ_WRITE_EXECUTE,
+        });
+
+        Ok(true)
+    }
+
+    unsafe fn unmap(&self, address: usize, size: usize) -> Result<(), i32> {
+        let end_address = self.validate(address, size)?;
+        self.split(address, end_address);
+        self.mappings
+            .borrow_mut()
+            .retain(|x| x.start_address < address || x.end_address > end_address);
+
+        Ok(())
+    }
+
+    unsafe fn protect(
+        &self,
+        address: usize,
+        size: usize,
+        permissions: MemoryPermissions,
+    ) -> Result<(), i32> {
+        let end_address = self.validate(address, size)?;
+
+        // Whole range must be mapped.
+        let mut covered_until = address;
+        for mapping in self.mappings.borrow().iter() {
+            if mapping.start_address <= covered_until && mapping.end_address > covered_until {
+                covered_until = mapping.end_address;
+            }
+        }
+
+        if covered_until < end_address {
+            return Err(OUT_OF_MEMORY);
+        }
+
+        self.split(address, end_address);
+        for mapping in self.mappings.borrow_mut().iter_mut() {
+            if mapping.start_address >= address && mapping.end_address <= end_address {
+                mapping.permissions = MemoryPermissions {
+                    shared: mapping.permissions.shared,
+                    ..permissions
+                };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_regions_should_cover_gaps_between_mappings() {
+        let backend = SimulatedBackend::new(0xFFFFF, 0x1000);
+        backend.add_mapping(0x10000, 0x20000);
+        backend.add_mapping(0x30000, 0x40000);
+
+        let mut regions = Vec::new();
+        backend.free_regions(&mut |x| regions.push(x)).unwrap();
+        assert_eq!(
+            regions,
+            vec![
+                MemoryMapEntry::new(0, 0xFFFF),
+                MemoryMapEntry::new(0x20000, 0x2FFFF),
+                MemoryMapEntry::new(0x40000, 0xFFFFF),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_should_fail_on_used_refused_and_invalid_addresses() {
+        let backend = SimulatedBackend::new(0xFFFFF, 0x1000);
+        backend.add_mapping(0x10000, 0x20000);
+        backend.refuse_mapping(0, 0x10000, 1);
+
+        assert_eq!(backend.map(0x1F000, 0x2000), Ok(false));
+        assert_eq!(backend.map(0xF000, 0x1000), Err(1));
+        assert_eq!(backend.map(0x20001, 0x1000), Err(INVALID_ARGUMENT));
+        assert_eq!(backend.map(0xFF000, 0x2000), Err(OUT_OF_MEMORY));
+        assert_eq!(backend.map(0x20000, 0x1000), Ok(true));
+        assert_eq!(
+            backend.protection_at(0x20000),
+            Some(MemoryPermissions::READ_WRITE_EXECUTE)
+        );
+    }
+
+    #[test]
+    fn protect_and_unmap_should_split_mappings() {
+        let backend = SimulatedBackend::new(0xFFFFF, 0x1000);
+        assert_eq!(backend.map(0x10000, 0x3000), Ok(true));
+
+        let read_only = MemoryPermissions {
+            read: true,
+            ..MemoryPermissions::default()
+        };
+
+        unsafe {
+            backend.protect(0x11000, 0x1000, read_only).unwrap();
+            assert_eq!(backend.mappings().len(), 3);
+            assert_eq!(backend.protection_at(0x11000), Some(read_only));
+            assert_eq!(
+                backend.protection_at(0x12000),
+                Some(MemoryPermissions::READ_WRITE_EXECUTE)
+            );
+
+            backend.unmap(0x10000, 0x2000).unwrap();
+            assert_eq!(backend.protection_at(0x11000), None);
+            assert_eq!(
+                backend.protect(0x11000, 0x2000, read_only),
+                Err(OUT_OF_MEMORY)
+            );
+        }
+    }
+}
@@ -0,0 +1,799 @@
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use crate::internal::buffer_allocator::{allocate, allocate_with_backend};
+use crate::internal::locator_header_finder::LocatorHeaderFinder;
+use crate::structs::errors::{BufferAllocationError, BufferSearchError, ItemAllocationError};
+use crate::structs::internal::{LocatorHeader, LocatorItem};
+use crate::structs::params::{
+    BufferAllocatorSettings, BufferManagerSettings, BufferSearchSettings,
+};
+use crate::structs::{PrivateAllocation, SafeLocatorItem};
+use crate::utilities::cached::get_sys_info;
+use crate::utilities::mathematics::round_up;
+use core::cmp::min;
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use spin::Mutex;
+
+#[cfg(feature = "alloc")]
+use {
+    crate::structs::internal::locator_header::LENGTH,
+    alloc::alloc::{alloc_zeroed, dealloc},
+    core::alloc::Layout,
+    core::mem::align_of,
+};
+
+#[cfg(not(feature = "alloc"))]
+use crate::structs::errors::BufferAllocationErrorKind;
+
+#[cfg(feature = "alloc")]
+use crate::{
+    internal::{locator_index::LocatorIndex, locator_snapshot},
+    structs::BufferSnapshot,
+};
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::{boxed::Box, vec::Vec};
+
+/// The process wide manager, used by [`Buffers`].
+///
+/// [`Buffers`]: crate::buffers::Buffers
+static GLOBAL_MANAGER: BufferManager<'static> = BufferManager {
+    chain: LocatorChain::Shared,
+    settings: BufferManagerSettings::new(),
+    #[cfg(feature = "alloc")]
+    index: Mutex::new(LocatorIndex::new()),
+};
+
+/// Where a manager's locator chain lives.
+enum LocatorChain {
+    /// The process wide chain, shared with every other instance of the library in the process
+    /// (found through a memory mapped file where supported).
+    Shared,
+
+    /// A chain of the manager's own, created on first use.
+    Private {
+        first_locator: AtomicPtr<LocatorHeader>,
+        init_lock: Mutex<()>,
+    },
+}
+
+/// Owns a locator chain, and hands out buffers from it.
+///
+/// # Remarks
+///
+/// [`Buffers`] is a thin wrapper around the process wide manager, [`BufferManager::global`].
+/// Use [`BufferManager::new_private`] for a separate pool of buffers, e.g. to isolate tests.
+///
+/// A private manager frees its locators and buffers when dropped; items taken from it borrow
+/// the manager, so none can outlive it.
+///
+/// A private manager's locators are placed within its address limits. With a backend, they are kept
+/// on the heap instead; the manager writes to its locators, and memory mapped through a backend may
+/// not be accessible from this process (e.g. it is in another process, or simulated).
+///
+/// [`Buffers`]: crate::buffers::Buffers
+pub struct BufferManager<'a> {
+    chain: LocatorChain,
+    settings: BufferManagerSettings<'a>,
+
+    /// Process local index of the items in the chain.
+    /// Without the `alloc` feature, the chain is searched one locator at a time.
+    #[cfg(feature = "alloc")]
+    index: Mutex<LocatorIndex>,
+}
+
+impl<'a> BufferManager<'a> {
+    /// Returns the process wide manager, which shares its buffers with every other instance of the
+    /// library in the process.
+    pub fn global() -> &'static BufferManager<'static> {
+        &GLOBAL_MANAGER
+    }
+
+    /// Creates a manager with its own locator chain, which is not shared with anyone else.
+    ///
+    /// # Remarks
+    ///
+    /// The chain is allocated on first use.
+    pub const fn new_private() -> Self {
+        Self::new_private_with_settings(BufferManagerSettings::new())
+    }
+
+    /// Creates a manager with its own locator chain and the given settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Limits of the manager, and how it allocates new buffers.
+    ///
+    /// # Remarks
+    ///
+    /// The chain is allocated on first use.
+    pub const fn new_private_with_settings(settings: BufferManagerSettings<'a>) -> Self {
+        Self {
+            chain: LocatorChain::Private {
+                first_locator: AtomicPtr::new(null_mut()),
+                init_lock: Mutex::new(()),
+            },
+            settings,
+            #[cfg(feature = "alloc")]
+            index: Mutex::new(LocatorIndex::new()),
+        }
+    }
+
+    /// Returns true if this manager's buffers are not shared with the rest of the process.
+    pub fn is_private(&self) -> bool {
+        matches!(self.chain, LocatorChain::Private { .. })
+    }
+
+    /// Returns the settings of this manager.
+    pub fn settings(&self) -> &BufferManagerSettings<'a> {
+        &self.settings
+    }
+
+    /// Gets a buffer with user specified requirements and provided alignment.
+    /// See [`Buffers::get_buffer_aligned`].
+    ///
+    /// [`Buffers::get_buffer_aligned`]: crate::buffers::Buffers::get_buffer_aligned
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub fn get_buffer_aligned(
+        &self,
+        settings: &BufferSearchSettings,
+        alignment: u32,
+    ) -> Result<SafeLocatorItem<'_>, BufferSearchError> {
+        let max_misalignment = alignment.saturating_sub(1);
+
+        // Adjust the size to include potential extra space for alignment.
+        let mut new_settings = *settings;
+        new_settings.size += max_misalignment;
+
+        let result = self.get_buffer(&new_settings)?;
+        unsafe {
+            let locator_item = result.item.get();
+            let address = (*locator_item).base_address.value + (*locator_item).position as usize;
+
+            // Use the round_up function to efficiently align the address.
+            let aligned_address = round_up(address, alignment as usize);
+            let delta = aligned_address - address;
+
+            // Adjust the position in the buffer accordingly.
+            (*locator_item).position += delta as u32;
+            Ok(result)
+        }
+    }
+
+    /// Gets a buffer with user specified requirements.
+    /// See [`Buffers::get_buffer`].
+    ///
+    /// # Remarks
+    ///
+    /// The search range is narrowed to the manager's address limits.
+    ///
+    /// [`Buffers::get_buffer`]: crate::buffers::Buffers::get_buffer
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub fn get_buffer(
+        &self,
+        settings: &BufferSearchSettings,
+    ) -> Result<SafeLocatorItem<'_>, BufferSearchError> {
+        let settings = self.settings.narrow_search(settings).ok_or_else(|| {
+            BufferSearchError::new(
+                *settings,
+                "Search range is outside of the buffer manager's address limits",
+            )
+        })?;
+
+        unsafe {
+            let first_locator = self
+                .first_locator()
+                .map_err(|error| allocation_failed(&settings, error))?;
+
+            self.get_buffer_in_chain(&settings, first_locator)
+        }
+    }
+
+    /// Takes a snapshot of every buffer of this manager.
+    /// See [`Buffers::get_buffer_snapshots`].
+    ///
+    /// [`Buffers::get_buffer_snapshots`]: crate::buffers::Buffers::get_buffer_snapshots
+    #[cfg(feature = "alloc")]
+    pub fn get_buffer_snapshots(&self) -> Vec<BufferSnapshot> {
+        unsafe {
+            match self.first_locator() {
+                Ok(locator) => locator_snapshot::get_snapshots(locator),
+                Err(_) => Vec::new(),
+            }
+        }
+    }
+
+    /// Returns the first locator of the chain, creating it if needed.
+    unsafe fn first_locator(&self) -> Result<*mut LocatorHeader, BufferAllocationError> {
+        match &self.chain {
+            LocatorChain::Shared => Ok(LocatorHeaderFinder::find()),
+            LocatorChain::Private {
+                first_locator,
+                init_lock,
+            } => {
+                let locator = first_locator.load(Ordering::Acquire);
+                if !locator.is_null() {
+                    return Ok(locator);
+                }
+
+                let _unused = init_lock.lock();
+                let mut locator = first_locator.load(Ordering::Acquire);
+                if locator.is_null() {
+                    let allocation = self.allocate_locator(None)?;
+                    locator = allocation.base_address.value as *mut LocatorHeader;
+                    (*locator).initialize(allocation.size as usize);
+                    first_locator.store(locator, Ordering::Release);
+                }
+
+                Ok(locator)
+            }
+        }
+    }
+
+    /// Allocates the memory of a new locator in a private chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `search` - Search which needs the new locator, if any. The locator is placed within its
+    ///   range if possible, so the buffers in its leftover space are likely to be useful for it.
+    ///
+    /// # Remarks
+    ///
+    /// The locator is always within the manager's address limits; or on the heap if the manager
+    /// has a backend, see [`BufferManager`].
+    fn allocate_locator(
+        &self,
+        search: Option<&BufferSearchSettings>,
+    ) -> Result<LocatorItem, BufferAllocationError> {
+        let sys_info = get_sys_info();
+        let mut settings = BufferAllocatorSettings {
+            min_address: self.settings.min_address,
+            max_address: min(self.settings.max_address, sys_info.max_address),
+            size: sys_info.allocation_granularity as u32,
+            retry_count: self.settings.retry_count,
+            brute_force: self.settings.brute_force,
+            ..BufferAllocatorSettings::new()
+        };
+
+        if self.settings.backend.is_some() {
+            return allocate_heap_locator(settings);
+        }
+
+        if let Some(search) = search {
+            let mut near_search = settings;
+            near_search.min_address = search.min_address;
+            near_search.max_address = search.max_address;
+            if let Ok(allocation) = allocate(&mut near_search) {
+                return Ok(allocation);
+            }
+        }
+
+        allocate(&mut settings)
+    }
+
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    unsafe fn get_buffer_in_chain(
+        &self,
+        settings: &BufferSearchSettings,
+        first_locator: *mut LocatorHeader,
+    ) -> Result<SafeLocatorItem<'_>, BufferSearchError> {
+        // Look for an existing buffer anywhere in the chain.
+        #[cfg(not(feature = "alloc"))]
+        {
+            let mut locator = first_locator;
+            while !locator.is_null() {
+                let item = (*locator).get_first_available_item_locked(
+                    settings.size,
+                    settings.min_address,
+                    settings.max_address,
+                );
+
+                if let Some(item) = item {
+                    return Ok(item);
+                }
+
+                locator = (*locator).next_locator_ptr.value;
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        {
+            let mut index = self.index.lock();
+            index.sync(first_locator);
+            let item = index.get_first_available_item_locked(
+                settings.size,
+                settings.min_address,
+                settings.max_address,
+            );
+
+            if let Some(item) = item {
+                return Ok(item);
+            }
+        }
+
+        // Otherwise allocate a new one, in the first locator with a free slot.
+        let mut locator = first_locator;
+        let mut num_locators = 1;
+        loop {
+            let result = (*locator).try_allocate_item_with(|| {
+                let mut allocator_settings = self.settings.get_allocator_settings(settings);
+                match self.settings.backend {
+                    Some(backend) => allocate_with_backend(&mut allocator_settings, backend),
+                    None => allocate(&mut allocator_settings),
+                }
+            });
+
+            match result {
+                Ok(new_item) => return Ok(new_item),
+                Err(ItemAllocationError::CannotAllocateMemory(error)) => {
+                    return Err(allocation_failed(settings, error));
+                }
+                Err(ItemAllocationError::NoSpaceInHeader) => {}
+            }
+
+            if num_locators >= self.settings.max_locators && !(*locator).has_next_locator() {
+                return Err(BufferSearchError::new(
+                    *settings,
+                    "Buffer manager has reached its maximum number of locators",
+                ));
+            }
+
+            num_locators += 1;
+            let next_locator = match &self.chain {
+                LocatorChain::Shared => {
+                    (*locator).get_next_locator_near(settings.min_address, settings.max_address)
+                }
+                LocatorChain::Private { .. } => {
+                    (*locator).get_next_locator_with(|| self.allocate_locator(Some(settings)))
+                }
+            };
+
+            locator = match next_locator {
+                Ok(locator) => locator,
+                Err(error) => return Err(BufferSearchError::new(*settings, error)),
+            };
+
+            // A new locator comes with buffers in its leftover space, which may be suitable.
+            let item = (*locator).get_first_available_item_locked(
+                settings.size,
+                settings.min_address,
+                settings.max_address,
+            );
+
+            if let Some(item) = item {
+                return Ok(item);
+            }
+        }
+    }
+}
+
+impl Default for BufferManager<'_> {
+    fn default() -> Self {
+        Self::new_private()
+    }
+}
+
+/// Frees the locators and buffers of a private manager.
+impl Drop for BufferManager<'_> {
+    fn drop(&mut self) {
+        let first_locator = match &self.chain {
+            LocatorChain::Shared => return,
+            LocatorChain::Private { first_locator, .. } => first_locator.load(Ordering::Acquire),
+        };
+
+        unsafe { self.free_chain(first_locator) }
+    }
+}
+
+impl BufferManager<'_> {
+    /// Frees every locator in a private chain, and every buffer allocated separately from them.
+    ///
+    /// # Remarks
+    ///
+    /// Locators are allocated one allocation granularity at a time, or on the heap with a backend.
+    /// Buffers inside that space (including further locators on platforms enforcing W^X) are
+    /// freed with their locator.
+    unsafe fn free_chain(&self, first_locator: *mut LocatorHeader) {
+        let locator_length = self.get_locator_length();
+
+        // Start and end of the allocation of the current locator.
+        let mut allocation = (0usize, 0usize);
+        let mut locator = first_locator;
+        while !locator.is_null() {
+            let header = &*locator;
+            let next_locator = header.next_locator_ptr.value;
+
+            let address = locator as usize;
+            let is_new_allocation = address < allocation.0 || address >= allocation.1;
+            if is_new_allocation {
+                allocation = (address, address + locator_length);
+            }
+
+            for index in 0..header.num_items as usize {
+                let item = &*header.get_item(index);
+                let start = item.base_address.value;
+                let is_in_locator = start >= allocation.0 && start < allocation.1;
+                if !is_in_locator && item.size != 0 {
+                    self.free_buffer(start, item.size as usize);
+                }
+            }
+
+            if is_new_allocation {
+                self.free_locator(allocation.0, locator_length);
+            }
+
+            locator = next_locator;
+        }
+    }
+
+    /// Returns the length of the allocations made for locators.
+    /// See [`BufferManager::allocate_locator`].
+    fn get_locator_length(&self) -> usize {
+        #[cfg(feature = "alloc")]
+        if self.settings.backend.is_some() {
+            return LENGTH;
+        }
+
+        get_sys_info().allocation_granularity as usize
+    }
+
+    /// Frees a buffer allocated by this manager, either through its backend or the OS.
+    unsafe fn free_buffer(&self, address: usize, size: usize) {
+        match self.settings.backend {
+            Some(backend) => {
+                let _ = backend.unmap(address, size);
+            }
+            None => free_os_memory(address, size),
+        }
+    }
+
+    /// Frees a locator allocated by this manager, either on the heap or the OS.
+    unsafe fn free_locator(&self, address: usize, size: usize) {
+        #[cfg(feature = "alloc")]
+        if self.settings.backend.is_some() {
+            dealloc(address as *mut u8, get_heap_locator_layout());
+            return;
+        }
+
+        free_os_memory(address, size);
+    }
+}
+
+/// Converts an error allocating memory for a search into the error returned from the search.
+fn allocation_failed(
+    settings: &BufferSearchSettings,
+    error: BufferAllocationError,
+) -> BufferSearchError {
+    BufferSearchError {
+        settings: *settings,
+        text: error.text,
+        #[cfg(feature = "alloc")]
+        allocation_error: Some(Box::new(error)),
+    }
+}
+
+/// Frees memory allocated from the OS in the current process.
+unsafe fn free_os_memory(address: usize, size: usize) {
+    if let Some(address) = NonNull::new(address as *mut u8) {
+        let process_id = get_sys_info().this_process_id;
+        drop(PrivateAllocation::new(address, size, process_id));
+    }
+}
+
+/// Returns the layout of locators kept on the heap.
+#[cfg(feature = "alloc")]
+fn get_heap_locator_layout() -> Layout {
+    Layout::from_size_align(LENGTH, align_of::<usize>()).unwrap()
+}
+
+/// Allocates a locator on the heap, for managers with a backend.
+///
+/// # Remarks
+///
+/// The locator has no space for buffers after its header; every buffer is allocated through the
+/// backend.
+#[cfg(feature = "alloc")]
+fn allocate_heap_locator(
+    settings: BufferAllocatorSettings,
+) -> Result<LocatorItem, BufferAllocationError> {
+    let memory = unsafe { alloc_zeroed(get_heap_locator_layout()) };
+    if memory.is_null() {
+        return Err(BufferAllocationError::new(
+            settings,
+            "Failed to allocate memory for LocatorHeader. Is this process out of memory?",
+        ));
+    }
+
+    Ok(LocatorItem::new(memory as usize, LENGTH as u32))
+}
+
+/// Managers with a backend keep their locators on the heap, which needs the `alloc` feature.
+#[cfg(not(feature = "alloc"))]
+fn allocate_heap_locator(
+    settings: BufferAllocatorSettings,
+) -> Result<LocatorItem, BufferAllocationError> {
+    Err(BufferAllocationError::with_kind(
+        settings,
+        "Buffer managers with a backend need the alloc feature, to keep their locators on the heap",
+        BufferAllocationErrorKind::InvalidSettings,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::Buffers;
+
+    #[test]
+    fn private_managers_should_not_share_buffers() {
+        let first = BufferManager::new_private();
+        let second = BufferManager::new_private();
+        let settings = BufferSearchSettings::new();
+
+        let item = first.get_buffer(&settings).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+        drop(item);
+
+        let not_shared = |snapshots: Vec<BufferSnapshot>| {
+            snapshots.iter().all(|x| x.base_address != base_address)
+        };
+
+        assert!(first
+            .get_buffer_snapshots()
+            .iter()
+            .any(|x| x.base_address == base_address));
+        assert!(not_shared(second.get_buffer_snapshots()));
+        assert!(not_shared(Buffers::get_buffer_snapshots()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn get_buffer_should_allocate_nearest_to_target() {
+        use crate::utilities::cached::get_sys_info;
+
+        // Larger than preallocated buffers, so a new one is always allocated.
+        let manager = BufferManager::new_private();
+        let target = get_sys_info().max_address / 2;
+        let settings = BufferSearchSettings::from_proximity(0x4000_0000, target, 0x10_0000);
+
+        let item = manager.get_buffer(&settings).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+
+        // Most of this range is free, so the buffer should be right next to the target,
+        // rather than at the start of the range.
+        assert!(base_address.abs_diff(target) < 0x1000_0000);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn get_buffer_should_allocate_with_huge_pages() {
+        use crate::utilities::linux_huge_pages::get_huge_page_size;
+
+        let huge_page_size = match get_huge_page_size() {
+            Some(size) => size,
+            None => return,
+        };
+
+        let manager = BufferManager::new_private();
+        let settings = BufferSearchSettings {
+            size: 0x10_0000,
+            huge_pages: true,
+            ..BufferSearchSettings::new()
+        };
+
+        let item = manager.get_buffer(&settings).unwrap();
+        let (base_address, size) = unsafe {
+            let item = &*item.item.get();
+            (item.base_address.value, item.size)
+        };
+
+        assert_eq!(base_address % huge_page_size, 0);
+        assert_eq!(size as usize % huge_page_size, 0);
+    }
+
+    /// Allows sharing a [`SimulatedBackend`] with a manager, which requires a `Sync` backend.
+    struct SyncBackend(Mutex<crate::backends::SimulatedBackend>);
+
+    impl crate::backends::MemoryBackend for SyncBackend {
+        fn allocation_granularity(&self) -> usize {
+            self.0.lock().allocation_granularity()
+        }
+
+        fn free_regions(
+            &self,
+            on_region: &mut dyn FnMut(crate::backends::MemoryMapEntry),
+        ) -> Result<(), i32> {
+            self.0.lock().free_regions(on_region)
+        }
+
+        fn map(&self, address: usize, size: usize) -> Result<bool, i32> {
+            self.0.lock().map(address, size)
+        }
+
+        unsafe fn unmap(&self, address: usize, size: usize) -> Result<(), i32> {
+            self.0.lock().unmap(address, size)
+        }
+
+        unsafe fn protect(
+            &self,
+            address: usize,
+            size: usize,
+            permissions: crate::structs::MemoryPermissions,
+        ) -> Result<(), i32> {
+            self.0.lock().protect(address, size, permissions)
+        }
+    }
+
+    #[test]
+    fn get_buffer_should_fail_outside_address_limits() {
+        let manager = BufferManager::new_private_with_settings(BufferManagerSettings {
+            min_address: 0x1000_0000,
+            ..BufferManagerSettings::new()
+        });
+
+        let settings = BufferSearchSettings {
+            max_address: 0x0FFF_FFFF,
+            ..BufferSearchSettings::new()
+        };
+
+        assert!(manager.get_buffer(&settings).is_err());
+    }
+
+    #[test]
+    fn get_buffer_should_allocate_with_backend_within_limits() {
+        use crate::backends::SimulatedBackend;
+
+        const MIN_ADDRESS: usize = 0x4000_0000;
+        let backend = SyncBackend(Mutex::new(SimulatedBackend::new(0x7FFF_FFFF, 0x10000)));
+        let manager = BufferManager::new_private_with_settings(BufferManagerSettings {
+            min_address: MIN_ADDRESS,
+            backend: Some(&backend),
+            ..BufferManagerSettings::new()
+        });
+
+        // Larger than preallocated buffers, so a new one is always allocated.
+        let settings = BufferSearchSettings {
+            size: 0x10_0000,
+            ..BufferSearchSettings::new()
+        };
+
+        let item = manager.get_buffer(&settings).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+        assert!(base_address >= MIN_ADDRESS);
+        assert!(backend
+            .0
+            .lock()
+            .mappings()
+            .iter()
+            .any(|x| x.start_address <= base_address && base_address < x.end_address));
+
+        drop(item);
+        drop(manager);
+        assert!(backend.0.lock().mappings().is_empty());
+    }
+
+    #[test]
+    fn get_buffer_should_only_allocate_buffers_with_backend() {
+        use crate::backends::SimulatedBackend;
+        use crate::structs::internal::locator_header::MAX_ITEM_COUNT;
+
+        let backend = SyncBackend(Mutex::new(SimulatedBackend::new(0x7FFF_FFFF, 0x10000)));
+        let manager = BufferManager::new_private_with_settings(BufferManagerSettings {
+            backend: Some(&backend),
+            ..BufferManagerSettings::new()
+        });
+
+        let settings = BufferSearchSettings {
+            size: 0x10000,
+            ..BufferSearchSettings::new()
+        };
+
+        // Hold the items, so the chain has to grow past the first locator.
+        let items: Vec<_> = (0..=MAX_ITEM_COUNT)
+            .map(|_| manager.get_buffer(&settings).unwrap())
+            .collect();
+
+        assert!(unsafe { (*manager.first_locator().unwrap()).has_next_locator() });
+
+        // Every buffer is in the simulated address space, none are in the locators' own space.
+        let mappings = backend.0.lock().mappings();
+        let snapshots = manager.get_buffer_snapshots();
+        assert_eq!(snapshots.len(), items.len());
+        assert!(snapshots.iter().all(|snapshot| mappings
+            .iter()
+            .any(|x| x.start_address <= snapshot.base_address
+                && snapshot.base_address < x.end_address)));
+
+        drop(items);
+        drop(manager);
+        assert!(backend.0.lock().mappings().is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn get_buffer_should_place_locators_within_limits() {
+        let min_address = get_sys_info().max_address / 2;
+        let manager = BufferManager::new_private_with_settings(BufferManagerSettings {
+            min_address,
+            ..BufferManagerSettings::new()
+        });
+
+        let item = manager.get_buffer(&BufferSearchSettings::new()).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+        let locator = unsafe { manager.first_locator().unwrap() } as usize;
+        assert!(base_address >= min_address);
+        assert!(locator >= min_address);
+    }
+
+    #[test]
+    fn get_buffer_should_not_exceed_max_locators() {
+        use crate::backends::SimulatedBackend;
+        use crate::structs::internal::locator_header::MAX_ITEM_COUNT;
+
+        let backend = SyncBackend(Mutex::new(SimulatedBackend::new(0x7FFF_FFFF, 0x10000)));
+        let manager = BufferManager::new_private_with_settings(BufferManagerSettings {
+            max_locators: 1,
+            backend: Some(&backend),
+            ..BufferManagerSettings::new()
+        });
+
+        let settings = BufferSearchSettings {
+            size: 0x10000,
+            ..BufferSearchSettings::new()
+        };
+
+        // Hold the items, so each search needs a new buffer.
+        let mut items = Vec::new();
+        while let Ok(item) = manager.get_buffer(&settings) {
+            items.push(item);
+            assert!(items.len() <= MAX_ITEM_COUNT as usize);
+        }
+
+        assert!(!items.is_empty());
+        assert!(!unsafe { (*manager.first_locator().unwrap()).has_next_locator() });
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn drop_should_free_private_buffers() {
+        use crate::backends::{LinuxBackend, MemoryBackend};
+
+        let manager = BufferManager::new_private();
+        let settings = BufferSearchSettings {
+            size: 0x10_0000,
+            ..BufferSearchSettings::new()
+        };
+
+        let item = manager.get_buffer(&settings).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+        let locator = unsafe { manager.first_locator().unwrap() } as usize;
+        drop(item);
+        drop(manager);
+
+        let mut free_regions = Vec::new();
+        LinuxBackend::new()
+            .free_regions(&mut |x| free_regions.push(x))
+            .unwrap();
+
+        let is_free = |address: usize| {
+            free_regions
+                .iter()
+                .any(|x| x.start_address <= address && address <= x.end_address)
+        };
+
+        assert!(is_free(base_address));
+        assert!(is_free(locator));
+    }
+
+    #[test]
+    fn global_manager_should_be_shared() {
+        assert!(!BufferManager::global().is_private());
+        assert!(BufferManager::new_private().is_private());
+        assert!(core::ptr::eq(
+            BufferManager::global(),
+            BufferManager::global()
+        ));
+    }
+}
@@ -1,17 +1,29 @@
+use crate::backends::MemoryBackend;
+use crate::buffer_manager::BufferManager;
 use crate::internal::buffer_allocator;
-use crate::internal::locator_header_finder::LocatorHeaderFinder;
-use crate::structs::errors::{BufferAllocationError, BufferSearchError, ItemAllocationError};
-use crate::structs::internal::LocatorHeader;
+use crate::structs::errors::{BufferAllocationError, BufferSearchError};
+use crate::structs::internal::LocatorItem;
 use crate::structs::params::{BufferAllocatorSettings, BufferSearchSettings};
 use crate::structs::{PrivateAllocation, SafeLocatorItem};
 use crate::utilities::disable_write_xor_execute::{
     disable_write_xor_execute, restore_write_xor_execute,
 };
 use crate::utilities::icache_clear::clear_instruction_cache;
-use crate::utilities::mathematics::round_up;
 use core::ptr::{copy_nonoverlapping, NonNull};
-use core::u8;
 
+#[cfg(target_os = "linux")]
+use crate::utilities::{cached::get_sys_info, linux_huge_pages::get_page_size_at};
+
+#[cfg(all(target_os = "linux", not(feature = "all_private"), feature = "alloc"))]
+use crate::{internal::locator_snapshot, structs::errors::ProcessInspectionError};
+
+#[cfg(feature = "alloc")]
+use crate::structs::BufferSnapshot;
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec::Vec;
+
+/// Static API of the library; buffers are taken from the process wide [`BufferManager::global`].
 pub struct Buffers {}
 
 impl Buffers {
@@ -28,16 +40,54 @@ impl Buffers {
     ///
     /// # Remarks
     ///
-    /// Allocating inside another process is only supported on Windows.
+    /// Allocating inside another process is only supported on Windows, and Linux (x86_64);
+    /// with the `external_processes` feature.
     pub fn allocate_private_memory(
         settings: &mut BufferAllocatorSettings,
     ) -> Result<PrivateAllocation, BufferAllocationError> {
         let alloc = buffer_allocator::allocate(settings)?;
-        Ok(PrivateAllocation::new(
+        #[allow(unused_mut)]
+        let mut result = PrivateAllocation::new(
             NonNull::new(alloc.base_address.value as *mut u8).unwrap(),
             alloc.size as usize,
             settings.target_process_id,
-        ))
+        );
+
+        #[cfg(target_os = "linux")]
+        if settings.huge_pages && settings.target_process_id == get_sys_info().this_process_id {
+            if let Some(page_size) = get_page_size_at(alloc.base_address.value) {
+                result.page_size = page_size;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Allocates some memory with user specified settings, through a custom backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Settings with which to allocate the memory. `target_process_id` and `huge_pages`
+    ///   are ignored; they're properties of the backend.
+    /// * `backend` - Backend used to find and map memory, e.g. a [`SimulatedBackend`].
+    ///
+    /// # Returns
+    ///
+    /// The address and size of the allocated memory.
+    ///
+    /// # Remarks
+    ///
+    /// Uses the same algorithm as [`Buffers::allocate_private_memory`], so allocation failures
+    /// (fragmented address spaces, tight windows) can be reproduced in tests.
+    ///
+    /// The memory is not freed automatically; use [`MemoryBackend::unmap`].
+    ///
+    /// [`SimulatedBackend`]: crate::backends::SimulatedBackend
+    pub fn allocate_with_backend<B: MemoryBackend + ?Sized>(
+        settings: &mut BufferAllocatorSettings,
+        backend: &B,
+    ) -> Result<LocatorItem, BufferAllocationError> {
+        buffer_allocator::allocate_with_backend(settings, backend)
     }
 
     /// Gets a buffer with user specified requirements and provided alignment.
@@ -67,29 +117,12 @@ impl Buffers {
     ///
     /// Returns an error if the memory cannot be allocated within the needed constraints when there
     /// is no existing suitable buffer.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn get_buffer_aligned(
         settings: &BufferSearchSettings,
         alignment: u32,
-    ) -> Result<SafeLocatorItem, BufferSearchError> {
-        let max_misalignment = alignment.saturating_sub(1);
-
-        // Adjust the size to include potential extra space for alignment.
-        let mut new_settings = *settings;
-        new_settings.size += max_misalignment;
-
-        let result = Self::get_buffer(&new_settings)?;
-        unsafe {
-            let locator_item = result.item.get();
-            let address = (*locator_item).base_address.value + (*locator_item).position as usize;
-
-            // Use the round_up function to efficiently align the address.
-            let aligned_address = round_up(address, alignment as usize);
-            let delta = aligned_address - address;
-
-            // Adjust the position in the buffer accordingly.
-            (*locator_item).position += delta as u32;
-            Ok(result)
-        }
+    ) -> Result<SafeLocatorItem<'static>, BufferSearchError> {
+        BufferManager::global().get_buffer_aligned(settings, alignment)
     }
 
     /// Gets a buffer with user specified requirements.
@@ -111,10 +144,11 @@ impl Buffers {
     ///
     /// Returns an error if the memory cannot be allocated within the needed constraints when there
     /// is no existing suitable buffer.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn get_buffer(
         settings: &BufferSearchSettings,
-    ) -> Result<SafeLocatorItem, BufferSearchError> {
-        unsafe { Self::get_buffer_recursive(settings, LocatorHeaderFinder::find()) }
+    ) -> Result<SafeLocatorItem<'static>, BufferSearchError> {
+        BufferManager::global().get_buffer(settings)
     }
 
     /// Call this method in order to safely be able to overwrite existing code that was
@@ -180,48 +214,42 @@ impl Buffers {
         restore_write_xor_execute(target, size);
         clear_instruction_cache(target, target.wrapping_add(size));
     }
-}
-
-impl Buffers {
-    unsafe fn get_buffer_recursive(
-        settings: &BufferSearchSettings,
-        locator: *mut LocatorHeader,
-    ) -> Result<SafeLocatorItem, BufferSearchError> {
-        let item = (*locator).get_first_available_item_locked(
-            settings.size,
-            settings.min_address,
-            settings.max_address,
-        );
-
-        // If not null and we have an item, return.
-        if item.is_some() {
-            return Ok(item.unwrap_unchecked());
-        }
 
-        // Otherwise try to allocate a new one.
-        let result =
-            (*locator).try_allocate_item(settings.size, settings.min_address, settings.max_address);
-
-        match result {
-            Ok(new_item) => Ok(new_item),
-            Err(error) => {
-                if error == ItemAllocationError::CannotAllocateMemory {
-                    return Err(BufferSearchError {
-                        settings: *settings,
-                        text: error.as_string(),
-                    });
-                }
+    /// Takes a snapshot of every buffer in the current process.
+    ///
+    /// # Returns
+    ///
+    /// The state of each buffer at the time it was read.
+    ///
+    /// # Remarks
+    ///
+    /// No locks are taken; buffers may be in use by other threads while they are read.
+    /// Intended for diagnostics. Requires the `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn get_buffer_snapshots() -> Vec<BufferSnapshot> {
+        BufferManager::global().get_buffer_snapshots()
+    }
 
-                let next_locator = (*locator).get_next_locator();
-                match next_locator {
-                    Ok(locator) => Self::get_buffer_recursive(settings, locator),
-                    Err(error) => Err(BufferSearchError {
-                        settings: *settings,
-                        text: error,
-                    }),
-                }
-            }
-        }
+    /// Takes a snapshot of every buffer in another process.
+    ///
+    /// # Arguments
+    ///
+    /// * `process_id` - ID of the process to inspect.
+    ///
+    /// # Returns
+    ///
+    /// The state of each buffer at the time it was read; in the same format as [`Buffers::get_buffer_snapshots`].
+    ///
+    /// # Remarks
+    ///
+    /// Only supported on Linux. The process' memory is read with `process_vm_readv`; it is never
+    /// locked or written to. The process must have the same pointer size as the current process, and
+    /// must have used the library (it fails otherwise).
+    #[cfg(all(target_os = "linux", not(feature = "all_private"), feature = "alloc"))]
+    pub fn get_buffer_snapshots_in_process(
+        process_id: u32,
+    ) -> Result<Vec<BufferSnapshot>, ProcessInspectionError> {
+        locator_snapshot::get_snapshots_from_process(process_id)
     }
 }
 
@@ -232,7 +260,7 @@ mod tests {
     use super::Buffers;
     use crate::{
         internal::locator_header_finder::LocatorHeaderFinder,
-        structs::params::{BufferAllocatorSettings, BufferSearchSettings},
+        structs::params::{BranchKind, BufferAllocatorSettings, BufferSearchSettings},
         utilities::cached::get_sys_info,
     };
     use std;
@@ -242,7 +270,7 @@ mod tests {
     fn allocate_private_memory_in_2gib() {
         let mut settings = BufferAllocatorSettings::new();
         settings.min_address = 0;
-        settings.max_address = std::i32::MAX as usize;
+        settings.max_address = i32::MAX as usize;
 
         let result = Buffers::allocate_private_memory(&mut settings);
         assert!(result.is_ok());
@@ -251,6 +279,79 @@ mod tests {
         assert!(item.size >= settings.size as usize);
     }
 
+    #[test]
+    #[cfg(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        feature = "external_processes"
+    ))]
+    fn allocate_private_memory_in_child_process() {
+        use crate::utilities::linux_process_memory::read_process_memory;
+        use std::process::Command;
+
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id();
+
+        let mut settings = BufferAllocatorSettings::new();
+        settings.target_process_id = pid;
+        settings.size = 4096;
+
+        let item = Buffers::allocate_private_memory(&mut settings).unwrap();
+        let base = item.base_address.as_ptr() as usize;
+        unsafe {
+            assert!(item.write_bytes(0, &[0xC3, 0x90]));
+            assert!(!item.write_bytes(item.size - 1, &[0, 0]));
+        }
+
+        let mut buffer = [0u8; 2];
+        read_process_memory(pid as i32, base, &mut buffer).unwrap();
+        assert_eq!(buffer, [0xC3, 0x90]);
+
+        // Memory is unmapped in the child on drop.
+        drop(item);
+        assert!(read_process_memory(pid as i32, base, &mut buffer).is_err());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", not(feature = "external_processes")))]
+    fn allocate_private_memory_in_other_process_requires_feature() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.target_process_id = u32::MAX;
+
+        let result = Buffers::allocate_private_memory(&mut settings);
+        assert_eq!(
+            result.err().unwrap().kind,
+            crate::structs::errors::BufferAllocationErrorKind::InvalidSettings
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn allocate_private_memory_with_huge_pages() {
+        use crate::utilities::linux_huge_pages::get_huge_page_size;
+
+        let huge_page_size = match get_huge_page_size() {
+            Some(size) => size,
+            None => return,
+        };
+
+        let mut settings = BufferAllocatorSettings::new();
+        settings.size = 4096;
+        settings.huge_pages = true;
+
+        let item = Buffers::allocate_private_memory(&mut settings).unwrap();
+        assert_eq!(item.base_address.as_ptr() as usize % huge_page_size, 0);
+        assert_eq!(item.size % huge_page_size, 0);
+
+        // Whether huge pages are obtained depends on system configuration.
+        assert!(
+            item.page_size == huge_page_size || item.page_size == get_sys_info().page_size as usize
+        );
+    }
+
     #[test]
     fn allocate_private_memory_up_to_max_address() {
         let mut settings = BufferAllocatorSettings::new();
@@ -271,6 +372,7 @@ mod tests {
             min_address: (get_sys_info().max_address / 2),
             max_address: get_sys_info().max_address,
             size: 4096,
+            ..BufferSearchSettings::new()
         };
 
         // Automatically dropped.
@@ -289,6 +391,7 @@ mod tests {
             min_address: (get_sys_info().max_address / 2),
             max_address: get_sys_info().max_address,
             size: 4096,
+            ..BufferSearchSettings::new()
         };
 
         let item = Buffers::get_buffer(&settings).unwrap();
@@ -319,6 +422,7 @@ mod tests {
             min_address: (get_sys_info().max_address / 2),
             max_address: get_sys_info().max_address,
             size: 4096,
+            ..BufferSearchSettings::new()
         };
 
         let item = Buffers::get_buffer(&settings).unwrap();
@@ -343,6 +447,84 @@ mod tests {
         }
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn append_code_with_can_emit_rip_relative_code() {
+        let settings = BufferSearchSettings {
+            min_address: (get_sys_info().max_address / 2),
+            max_address: get_sys_info().max_address,
+            size: 4096,
+            ..BufferSearchSettings::new()
+        };
+
+        let item = Buffers::get_buffer(&settings).unwrap();
+        let data_address = unsafe { item.append_copy(&0x1234567812345678u64) };
+
+        unsafe {
+            let code_ptr = item.append_code_with(|address, buffer| {
+                // mov rax, [rip + disp32]; ret
+                let displacement = (data_address as i64 - (address + 7) as i64) as i32;
+                buffer[..3].copy_from_slice(&[0x48, 0x8B, 0x05]);
+                buffer[3..7].copy_from_slice(&displacement.to_le_bytes());
+                buffer[7] = 0xC3;
+                8
+            });
+
+            let func: extern "C" fn() -> u64 = std::mem::transmute(code_ptr);
+            assert_eq!(func(), 0x1234567812345678);
+        }
+    }
+
+    #[test]
+    fn append_copy_should_write_value_rather_than_its_address() {
+        let item = Buffers::get_buffer(&BufferSearchSettings::new()).unwrap();
+
+        unsafe {
+            let value = 0x1234567812345678u64;
+            let address = item.append_copy(&value);
+            assert_eq!(
+                core::ptr::read_unaligned(address as *const u64),
+                0x1234567812345678
+            );
+        }
+    }
+
+    #[test]
+    fn typed_appends_should_be_aligned() {
+        let item = Buffers::get_buffer(&BufferSearchSettings::new()).unwrap();
+
+        unsafe {
+            item.append_bytes(&[1]);
+            let value = item.append_value(0x1234567812345678u64);
+            assert!((value.as_ptr() as usize).is_multiple_of(8));
+            assert_eq!(*value.as_ref(), 0x1234567812345678);
+
+            item.append_bytes(&[1]);
+            let slice = item.append_slice(&[1u32, 2, 3]);
+            assert!((slice.as_ptr() as usize).is_multiple_of(4));
+            assert_eq!(slice, &[1, 2, 3]);
+
+            assert_eq!(item.append_str("reloaded"), "reloaded");
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn append_code_region_can_be_called_and_patched() {
+        let item = Buffers::get_buffer(&BufferSearchSettings::new()).unwrap();
+
+        unsafe {
+            // mov eax, 1; ret
+            let region = item.append_code_region(&[0xB8, 0x01, 0x00, 0x00, 0x00, 0xC3]);
+            let func: extern "C" fn() -> u32 = region.as_function();
+            assert_eq!(func(), 1);
+
+            // mov eax, 2
+            assert!(region.overwrite(&[0xB8, 0x02, 0x00, 0x00, 0x00]));
+            assert_eq!(func(), 2);
+        }
+    }
+
     #[cfg(target_arch = "aarch64")]
     #[test]
     fn memory_is_executable_aarch64() {
@@ -350,6 +532,7 @@ mod tests {
             min_address: (get_sys_info().max_address / 2),
             max_address: get_sys_info().max_address,
             size: 4096,
+            ..BufferSearchSettings::new()
         };
 
         let item = Buffers::get_buffer(&settings).unwrap();
@@ -385,6 +568,7 @@ mod tests {
             min_address: (get_sys_info().max_address / 2),
             max_address: get_sys_info().max_address,
             size: 4096,
+            ..BufferSearchSettings::new()
         };
 
         // The function should succeed with these settings.
@@ -412,14 +596,14 @@ mod tests {
     #[test]
     fn get_buffer_with_proximity() {
         const SIZE: usize = 4096;
-        let base_address = get_sys_info().max_address - (std::i32::MAX as usize);
+        let base_address = get_sys_info().max_address - (i32::MAX as usize);
 
         unsafe {
             LocatorHeaderFinder::reset();
         }
 
         let item = Buffers::get_buffer(&BufferSearchSettings::from_proximity(
-            std::i32::MAX as usize,
+            i32::MAX as usize,
             base_address,
             SIZE,
         ));
@@ -434,4 +618,25 @@ mod tests {
             assert!(offset < (i32::MAX as i64));
         }
     }
+
+    #[test]
+    fn get_buffer_for_branch_can_reach_target() {
+        const SIZE: usize = 4096;
+        let target = get_sys_info().max_address - (i32::MAX as usize);
+
+        unsafe {
+            LocatorHeaderFinder::reset();
+        }
+
+        let kind = BranchKind::Arm64Branch;
+        let item =
+            Buffers::get_buffer(&BufferSearchSettings::from_branch(kind, target, SIZE)).unwrap();
+
+        assert!(item.can_reach(kind, target));
+        unsafe {
+            item.append_bytes(&[0; SIZE]);
+        }
+
+        assert!(item.can_reach(kind, target));
+    }
 }
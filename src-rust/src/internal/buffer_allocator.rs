@@ -1,16 +1,40 @@
-use crate::structs::errors::BufferAllocationError;
+use crate::backends::MemoryBackend;
+use crate::structs::errors::{BufferAllocationError, BufferAllocationErrorKind};
 use crate::structs::internal::LocatorItem;
-use crate::structs::params::BufferAllocatorSettings;
+use crate::structs::params::buffer_allocator_settings::round_up_size;
+use crate::structs::params::{AllocationPlacement, BufferAllocatorSettings};
 use crate::utilities::address_range::AddressRange;
 use crate::utilities::mathematics::{
-    add_with_overflow_cap, round_down, round_up, subtract_with_underflow_cap,
+    add_with_overflow_cap, min, round_down, round_up, subtract_with_underflow_cap,
 };
+use core::cmp::max;
+use core::iter::StepBy;
+use core::ops::RangeInclusive;
+
+use crate::utilities::map_parser_utilities::{push_free_region, FreeRegions, MemoryMapEntry};
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec::Vec;
+
+/// Candidate addresses for a buffer, one per free region.
+#[cfg(feature = "alloc")]
+pub type BufferAddresses = Vec<usize>;
+
+/// Candidate addresses for a buffer, one per free region.
+#[cfg(not(feature = "alloc"))]
+pub type BufferAddresses =
+    crate::structs::FixedVec<usize, { crate::utilities::map_parser_utilities::MAX_FREE_REGIONS }>;
+
+/// Maximum number of addresses tried in a single brute force pass over the free regions.
+/// See [`get_brute_force_buffer_addresses`].
+pub const MAX_BRUTE_FORCE_ATTEMPTS: usize = 16384;
 
 #[cfg_attr(feature = "size_opt", optimize(size))]
 pub fn allocate(
     settings: &mut BufferAllocatorSettings,
 ) -> Result<LocatorItem, BufferAllocationError> {
     settings.sanitize();
+    validate_settings(settings, settings.allocation_granularity as usize)?;
 
     #[cfg(target_os = "windows")]
     return crate::internal::buffer_allocator_windows::allocate_windows(settings);
@@ -26,6 +50,260 @@ pub fn allocate(
     crate::internal::buffer_allocator_mmap_rs::allocate_mmap_rs(settings)
 }
 
+/// Allocates a buffer through the given backend, see [`Buffers::allocate_with_backend`].
+///
+/// [`Buffers::allocate_with_backend`]: crate::buffers::Buffers::allocate_with_backend
+#[cfg_attr(feature = "size_opt", optimize(size))]
+pub fn allocate_with_backend<B: MemoryBackend + ?Sized>(
+    settings: &mut BufferAllocatorSettings,
+    backend: &B,
+) -> Result<LocatorItem, BufferAllocationError> {
+    settings.sanitize();
+    let granularity = get_granularity(backend, settings);
+    if let Some(size) = round_up_size(settings.size, granularity) {
+        settings.size = size;
+    }
+
+    validate_settings(settings, granularity)?;
+    allocate_in_backend(backend, settings, "Failed to allocate buffer")
+}
+
+/// Rejects sanitized settings which can never be satisfied.
+///
+/// # Arguments
+///
+/// * `settings` - Sanitized settings.
+/// * `granularity` - Granularity the size should have been rounded up to.
+fn validate_settings(
+    settings: &BufferAllocatorSettings,
+    granularity: usize,
+) -> Result<(), BufferAllocationError> {
+    let invalid = |text| {
+        Err(BufferAllocationError::with_kind(
+            *settings,
+            text,
+            BufferAllocationErrorKind::InvalidSettings,
+        ))
+    };
+
+    if !granularity.is_power_of_two() {
+        return invalid("Allocation granularity must be a power of 2, no larger than 2^31");
+    }
+
+    if !(settings.size as usize).is_multiple_of(granularity) {
+        return invalid("Requested size overflows when rounded up to the allocation granularity");
+    }
+
+    if settings.min_address >= settings.max_address
+        || settings.size as usize > settings.max_address - settings.min_address
+    {
+        return invalid("Requested size does not fit between min and max address");
+    }
+
+    Ok(())
+}
+
+/// Returns the granularity to align allocations to; the coarser of the backend's and the requested one.
+fn get_granularity<B: MemoryBackend + ?Sized>(
+    backend: &B,
+    settings: &BufferAllocatorSettings,
+) -> usize {
+    max(
+        backend.allocation_granularity(),
+        settings.allocation_granularity as usize,
+    )
+}
+
+/// Allocates a buffer by searching the free regions reported by a backend.
+///
+/// # Arguments
+///
+/// * `backend` - Backend to find and map memory with.
+/// * `settings` - Sanitized and validated settings.
+/// * `text` - Error message if no buffer could be allocated.
+pub fn allocate_in_backend<B: MemoryBackend + ?Sized>(
+    backend: &B,
+    settings: &BufferAllocatorSettings,
+    text: &'static str,
+) -> Result<LocatorItem, BufferAllocationError> {
+    let mut allocation = BackendAllocation {
+        backend,
+        settings,
+        granularity: get_granularity(backend, settings),
+        diagnostics: AllocationDiagnostics::new(),
+    };
+
+    for _ in 0..settings.retry_count {
+        allocation.diagnostics = AllocationDiagnostics::new();
+        let regions = collect_free_regions(settings, &mut allocation.diagnostics, |on_region| {
+            backend.free_regions(on_region)
+        })?;
+
+        if settings.placement != AllocationPlacement::Lowest {
+            for addr in get_preferred_buffer_addresses(&regions, settings, allocation.granularity) {
+                if let Some(item) = allocation.try_map_at(addr) {
+                    return Ok(item);
+                }
+            }
+        }
+
+        for region in &regions {
+            if region.start_address > settings.max_address {
+                break;
+            }
+
+            if let Some(item) = allocation.try_allocate_buffer(region) {
+                return Ok(item);
+            }
+        }
+
+        // See remarks on 'brute_force' in BufferAllocatorSettings.
+        if settings.brute_force {
+            let mut attempts_left = MAX_BRUTE_FORCE_ATTEMPTS;
+            for region in &regions {
+                if region.start_address > settings.max_address || attempts_left == 0 {
+                    break;
+                }
+
+                if let Some(item) =
+                    allocation.try_allocate_buffer_brute_force(region, &mut attempts_left)
+                {
+                    return Ok(item);
+                }
+            }
+        }
+    }
+
+    Err(allocation.diagnostics.into_error(*settings, text))
+}
+
+/// Collects the free regions a buffer satisfying the settings could be placed in.
+///
+/// # Arguments
+///
+/// * `settings` - Settings of the allocation.
+/// * `diagnostics` - Diagnostics of the allocation; told about every free region, including those
+///   outside of the requested range.
+/// * `read_regions` - Reads the free regions, passing each one to the given callback.
+///   Returns the OS error code on failure.
+///
+/// # Remarks
+///
+/// Regions outside the requested range are never stored, so the list only fills up if the range
+/// itself is fragmented. Without the `alloc` feature, an error is returned if it fills up.
+pub fn collect_free_regions(
+    settings: &BufferAllocatorSettings,
+    diagnostics: &mut AllocationDiagnostics,
+    read_regions: impl FnOnce(&mut dyn FnMut(MemoryMapEntry)) -> Result<(), i32>,
+) -> Result<FreeRegions, BufferAllocationError> {
+    let mut regions = FreeRegions::new();
+    let mut is_full = false;
+    let result = read_regions(&mut |region| {
+        diagnostics.on_free_region(region.start_address, region.end_address, settings);
+        if region.end_address >= settings.min_address
+            && region.start_address <= settings.max_address
+        {
+            is_full |= !push_free_region(&mut regions, region);
+        }
+    });
+
+    if let Err(os_error) = result {
+        return Err(BufferAllocationError::with_kind(
+            *settings,
+            "Failed to read memory map of process",
+            BufferAllocationErrorKind::ProcessUnavailable { os_error },
+        ));
+    }
+
+    if is_full {
+        return Err(BufferAllocationError::with_kind(
+            *settings,
+            "Too many free regions in the requested range",
+            BufferAllocationErrorKind::TooManyFreeRegions,
+        ));
+    }
+
+    Ok(regions)
+}
+
+/// State of a single call to [`allocate_in_backend`].
+struct BackendAllocation<'a, B: MemoryBackend + ?Sized> {
+    backend: &'a B,
+    settings: &'a BufferAllocatorSettings,
+    granularity: usize,
+    diagnostics: AllocationDiagnostics,
+}
+
+impl<B: MemoryBackend + ?Sized> BackendAllocation<'_, B> {
+    fn try_allocate_buffer(&mut self, entry: &MemoryMapEntry) -> Option<LocatorItem> {
+        let buffer: &mut [usize; 4] = &mut [0; 4];
+        let addresses = unsafe {
+            get_possible_buffer_addresses(
+                self.settings.min_address,
+                self.settings.max_address,
+                entry.start_address,
+                entry.end_address,
+                self.settings.size as usize,
+                self.granularity,
+                buffer,
+            )
+        };
+
+        for addr in addresses {
+            if let Some(item) = self.try_map_at(*addr) {
+                return Some(item);
+            }
+        }
+
+        None
+    }
+
+    fn try_allocate_buffer_brute_force(
+        &mut self,
+        entry: &MemoryMapEntry,
+        attempts_left: &mut usize,
+    ) -> Option<LocatorItem> {
+        let addresses = get_brute_force_buffer_addresses(
+            self.settings.min_address,
+            self.settings.max_address,
+            entry.start_address,
+            entry.end_address,
+            self.settings.size as usize,
+            self.granularity,
+        );
+
+        for addr in addresses {
+            if *attempts_left == 0 {
+                return None;
+            }
+
+            *attempts_left -= 1;
+            if let Some(item) = self.try_map_at(addr) {
+                return Some(item);
+            }
+        }
+
+        None
+    }
+
+    fn try_map_at(&mut self, addr: usize) -> Option<LocatorItem> {
+        // Null page may be mappable with elevated privileges, but a buffer can never start at 0.
+        if addr == 0 {
+            return None;
+        }
+
+        let size = self.settings.size;
+        match self.backend.map(addr, size as usize) {
+            Ok(true) => Some(LocatorItem::new(addr, size)),
+            Ok(false) => None,
+            Err(os_error) => {
+                self.diagnostics.on_map_error(os_error);
+                None
+            }
+        }
+    }
+}
+
 pub unsafe fn get_possible_buffer_addresses(
     minimum_ptr: usize,
     maximum_ptr: usize,
@@ -112,9 +390,225 @@ pub unsafe fn get_possible_buffer_addresses(
     &results[0..num_items]
 }
 
+/// Collects information about an allocation attempt, used for reporting why it failed.
+pub struct AllocationDiagnostics {
+    regions_examined: usize,
+    nearest_free_region: Option<(usize, usize)>,
+    nearest_distance: usize,
+    os_error: Option<i32>,
+}
+
+impl AllocationDiagnostics {
+    pub fn new() -> Self {
+        Self {
+            regions_examined: 0,
+            nearest_free_region: None,
+            nearest_distance: usize::MAX,
+            os_error: None,
+        }
+    }
+
+    /// Records a free region which was examined.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start address of the free region.
+    /// * `end` - End address of the free region.
+    /// * `settings` - Settings of the allocation.
+    pub fn on_free_region(&mut self, start: usize, end: usize, settings: &BufferAllocatorSettings) {
+        self.regions_examined += 1;
+
+        let distance = if end <= settings.min_address {
+            settings.min_address - end
+        } else {
+            start.saturating_sub(settings.max_address)
+        };
+
+        if distance < self.nearest_distance {
+            self.nearest_distance = distance;
+            self.nearest_free_region = Some((start, end));
+        }
+    }
+
+    /// Records an error returned by the OS when mapping memory at an address believed to be free.
+    ///
+    /// # Remarks
+    ///
+    /// Errors caused by the address being taken in the meantime should not be recorded.
+    pub fn on_map_error(&mut self, os_error: i32) {
+        self.os_error = Some(os_error);
+    }
+
+    /// Creates the error to return when the allocation failed.
+    pub fn into_error(
+        self,
+        settings: BufferAllocatorSettings,
+        text: &'static str,
+    ) -> BufferAllocationError {
+        let kind = match self.os_error {
+            Some(os_error) => BufferAllocationErrorKind::MapFailed { os_error },
+            None => BufferAllocationErrorKind::NoSpaceInRange,
+        };
+
+        BufferAllocationError {
+            regions_examined: self.regions_examined,
+            nearest_free_region: self.nearest_free_region,
+            ..BufferAllocationError::with_kind(settings, text, kind)
+        }
+    }
+}
+
+/// Gets every address in the given page at which a buffer could be allocated, i.e. every address
+/// aligned to `allocation_granularity` such that the buffer lies within both the page and
+/// the min-max range.
+///
+/// # Remarks
+///
+/// This is the brute force counterpart of [`get_possible_buffer_addresses`], used when
+/// allocating at the few addresses returned by that function fails. Callers should cap the number
+/// of attempts, see [`MAX_BRUTE_FORCE_ATTEMPTS`].
+pub fn get_brute_force_buffer_addresses(
+    minimum_ptr: usize,
+    maximum_ptr: usize,
+    page_start: usize,
+    page_end: usize,
+    buf_size: usize,
+    allocation_granularity: usize,
+) -> StepBy<RangeInclusive<usize>> {
+    let (first, last) = get_buffer_address_bounds(
+        minimum_ptr,
+        maximum_ptr,
+        page_start,
+        page_end,
+        buf_size,
+        allocation_granularity,
+    )
+    .unwrap_or((1, 0)); // does not fit, empty range.
+
+    (first..=last).step_by(allocation_granularity)
+}
+
+/// Gets the address in the given page at which a buffer should preferably be allocated, according
+/// to the given placement.
+///
+/// # Arguments
+///
+/// * `placement` - Where the buffer should preferably be placed.
+/// * `target` - Address to place the buffer near, when `placement` is [`AllocationPlacement::Nearest`].
+///
+/// # Returns
+///
+/// The allocation granularity aligned address, or `None` if the buffer does not fit inside both
+/// the page and the min-max range.
+#[allow(clippy::too_many_arguments)]
+pub fn get_preferred_buffer_address(
+    placement: AllocationPlacement,
+    target: usize,
+    minimum_ptr: usize,
+    maximum_ptr: usize,
+    page_start: usize,
+    page_end: usize,
+    buf_size: usize,
+    allocation_granularity: usize,
+) -> Option<usize> {
+    let (first, last) = get_buffer_address_bounds(
+        minimum_ptr,
+        maximum_ptr,
+        page_start,
+        page_end,
+        buf_size,
+        allocation_granularity,
+    )?;
+
+    match placement {
+        AllocationPlacement::Lowest => Some(first),
+        AllocationPlacement::Highest => Some(last),
+        AllocationPlacement::Nearest => {
+            let below = round_down(target, allocation_granularity).clamp(first, last);
+            let above = round_down(
+                add_with_overflow_cap(target, allocation_granularity - 1),
+                allocation_granularity,
+            )
+            .clamp(first, last);
+
+            if target.abs_diff(below) <= target.abs_diff(above) {
+                Some(below)
+            } else {
+                Some(above)
+            }
+        }
+    }
+}
+
+/// Gets the preferred address (see [`get_preferred_buffer_address`]) in each of the given free regions,
+/// ordered from most to least preferred.
+///
+/// # Remarks
+///
+/// Not used for [`AllocationPlacement::Lowest`], the regular candidates from
+/// [`get_possible_buffer_addresses`] already start at the lowest address.
+pub fn get_preferred_buffer_addresses(
+    free_regions: &[MemoryMapEntry],
+    settings: &BufferAllocatorSettings,
+    allocation_granularity: usize,
+) -> BufferAddresses {
+    let mut results: BufferAddresses = free_regions
+        .iter()
+        .filter_map(|region| {
+            get_preferred_buffer_address(
+                settings.placement,
+                settings.target_address,
+                settings.min_address,
+                settings.max_address,
+                region.start_address,
+                region.end_address,
+                settings.size as usize,
+                allocation_granularity,
+            )
+        })
+        .collect();
+
+    match settings.placement {
+        AllocationPlacement::Lowest => results.sort_unstable(),
+        AllocationPlacement::Highest => results.sort_unstable_by(|a, b| b.cmp(a)),
+        AllocationPlacement::Nearest => {
+            results.sort_unstable_by_key(|x| x.abs_diff(settings.target_address))
+        }
+    }
+
+    results
+}
+
+/// Gets the lowest and highest allocation granularity aligned address at which the buffer fits
+/// inside both the page and the min-max range.
+fn get_buffer_address_bounds(
+    minimum_ptr: usize,
+    maximum_ptr: usize,
+    page_start: usize,
+    page_end: usize,
+    buf_size: usize,
+    allocation_granularity: usize,
+) -> Option<(usize, usize)> {
+    // Region where both the page and min-max range overlap.
+    let start = max(minimum_ptr, page_start);
+    let end = min(maximum_ptr, page_end);
+    if end <= start || end - start < buf_size {
+        return None;
+    }
+
+    let first = round_up(start, allocation_granularity);
+    let last = round_down(end - buf_size, allocation_granularity);
+    if first > last {
+        return None;
+    }
+
+    Some((first, last))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backends::SimulatedBackend;
     #[cfg(target_os = "windows")]
     use crate::internal::buffer_allocator_windows::{Kernel32, LocalKernel32};
     use crate::utilities::cached::get_sys_info;
@@ -289,6 +783,387 @@ mod tests {
         }
     }
 
+    #[test]
+    fn brute_force_returns_every_aligned_address_in_overlap() {
+        let min_ptr = 100000;
+        let max_ptr = 400000;
+        let page_start = 50000;
+        let page_end = 300000;
+        let buf_size = 30000;
+
+        let result: Vec<usize> = get_brute_force_buffer_addresses(
+            min_ptr,
+            max_ptr,
+            page_start,
+            page_end,
+            buf_size,
+            ALLOCATION_GRANULARITY,
+        )
+        .collect();
+
+        // Overlap is 100000..300000, so buffer may start between 100000 and 270000.
+        assert_eq!(result, vec![131072, 196608, 262144]);
+    }
+
+    #[test]
+    fn brute_force_returns_nothing_when_buffer_does_not_fit_in_overlap() {
+        let min_ptr = 100000;
+        let max_ptr = 200000;
+        let page_start = 150000;
+        let page_end = 400000;
+        let buf_size = 60000;
+
+        let result = get_brute_force_buffer_addresses(
+            min_ptr,
+            max_ptr,
+            page_start,
+            page_end,
+            buf_size,
+            ALLOCATION_GRANULARITY,
+        )
+        .count();
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn brute_force_returns_nothing_when_page_does_not_overlap_with_min_max() {
+        let result = get_brute_force_buffer_addresses(
+            100000,
+            200000,
+            300000,
+            400000,
+            4096,
+            ALLOCATION_GRANULARITY,
+        )
+        .count();
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn brute_force_returns_nothing_when_no_aligned_address_fits() {
+        // Overlap is large enough, but no granularity aligned address fits.
+        let result = get_brute_force_buffer_addresses(
+            70000,
+            140000,
+            0,
+            usize::MAX,
+            65536,
+            ALLOCATION_GRANULARITY,
+        )
+        .count();
+
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn preferred_address_lowest_and_highest() {
+        let args = (100000, 400000, 50000, 300000, 30000, ALLOCATION_GRANULARITY);
+        let get = |placement| {
+            get_preferred_buffer_address(
+                placement, 0, args.0, args.1, args.2, args.3, args.4, args.5,
+            )
+        };
+
+        assert_eq!(get(AllocationPlacement::Lowest), Some(131072));
+        assert_eq!(get(AllocationPlacement::Highest), Some(262144));
+    }
+
+    #[test]
+    fn preferred_address_nearest_picks_closest_aligned_address() {
+        let get = |target| {
+            get_preferred_buffer_address(
+                AllocationPlacement::Nearest,
+                target,
+                0,
+                usize::MAX,
+                0,
+                1000000,
+                30000,
+                ALLOCATION_GRANULARITY,
+            )
+        };
+
+        assert_eq!(get(200000), Some(196608));
+        assert_eq!(get(250000), Some(262144));
+
+        // Clamped to where the buffer still fits.
+        assert_eq!(get(999999), Some(917504));
+    }
+
+    #[test]
+    fn preferred_address_returns_none_when_buffer_does_not_fit() {
+        let result = get_preferred_buffer_address(
+            AllocationPlacement::Nearest,
+            150000,
+            100000,
+            200000,
+            150000,
+            400000,
+            60000,
+            ALLOCATION_GRANULARITY,
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    fn preferred_addresses_are_sorted_by_distance_to_target() {
+        let regions = [
+            MemoryMapEntry::new(0, 0x100000),
+            MemoryMapEntry::new(0x400000, 0x500000),
+            MemoryMapEntry::new(0x800000, 0x900000),
+        ];
+
+        let settings = BufferAllocatorSettings::from_proximity(0x800000, 0x7F0000, 4096);
+        let result = get_preferred_buffer_addresses(&regions, &settings, 4096);
+        assert_eq!(result[..], [0x800000, 0x4FF000, 0xFF000]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn can_allocate_nearest_to_target() {
+        let target = get_sys_info().max_address / 2;
+        let mut settings = BufferAllocatorSettings::from_proximity(0x4000_0000, target, 4096);
+        let item = allocate(&mut settings).unwrap();
+
+        // Most of this range is free, so the allocation should be right next to the target.
+        let base_addr = item.base_address.value;
+        assert!(base_addr.abs_diff(target) < 0x1000_0000);
+        free(item);
+    }
+
+    #[test]
+    fn diagnostics_should_report_nearest_free_region() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0x100000;
+        settings.max_address = 0x200000;
+
+        let mut diagnostics = AllocationDiagnostics::new();
+        diagnostics.on_free_region(0x10000, 0x20000, &settings);
+        diagnostics.on_free_region(0x210000, 0x220000, &settings);
+        diagnostics.on_free_region(0x400000, 0x500000, &settings);
+
+        let error = diagnostics.into_error(settings, "test");
+        assert_eq!(error.kind, BufferAllocationErrorKind::NoSpaceInRange);
+        assert_eq!(error.regions_examined, 3);
+        assert_eq!(error.nearest_free_region, Some((0x210000, 0x220000)));
+    }
+
+    #[test]
+    fn diagnostics_should_report_os_error() {
+        let settings = BufferAllocatorSettings::new();
+        let mut diagnostics = AllocationDiagnostics::new();
+        diagnostics.on_map_error(13);
+
+        let error = diagnostics.into_error(settings, "test");
+        assert_eq!(
+            error.kind,
+            BufferAllocationErrorKind::MapFailed { os_error: 13 }
+        );
+    }
+
+    #[test]
+    fn collect_free_regions_should_only_keep_regions_in_range() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0x20000;
+        settings.max_address = 0x3FFFF;
+
+        let mut diagnostics = AllocationDiagnostics::new();
+        let regions = collect_free_regions(&settings, &mut diagnostics, |on_region| {
+            on_region(MemoryMapEntry::new(0, 0xFFFF));
+            on_region(MemoryMapEntry::new(0x18000, 0x27FFF));
+            on_region(MemoryMapEntry::new(0x30000, 0x4FFFF));
+            on_region(MemoryMapEntry::new(0x50000, 0x5FFFF));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            regions[..],
+            [
+                MemoryMapEntry::new(0x18000, 0x27FFF),
+                MemoryMapEntry::new(0x30000, 0x4FFFF),
+            ]
+        );
+
+        // Regions outside the range are still reported in errors.
+        let error = diagnostics.into_error(settings, "test");
+        assert_eq!(error.regions_examined, 4);
+    }
+
+    #[test]
+    fn collect_free_regions_should_report_read_error() {
+        let settings = BufferAllocatorSettings::new();
+        let mut diagnostics = AllocationDiagnostics::new();
+        let error = collect_free_regions(&settings, &mut diagnostics, |_| Err(13))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error.kind,
+            BufferAllocationErrorKind::ProcessUnavailable { os_error: 13 }
+        );
+    }
+
+    #[test]
+    fn allocate_should_reject_invalid_settings() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0x200000;
+        settings.max_address = 0x100000;
+
+        let error = allocate(&mut settings).err().unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::InvalidSettings);
+    }
+
+    #[test]
+    fn allocate_should_reject_size_which_overflows_granularity() {
+        let mut settings = BufferAllocatorSettings {
+            size: u32::MAX,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let error = allocate(&mut settings).err().unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::InvalidSettings);
+        assert_eq!(settings.size, u32::MAX);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn allocate_should_reject_granularity_above_2_31() {
+        let mut settings = BufferAllocatorSettings {
+            allocation_granularity: (1 << 31) + 1,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let error = allocate(&mut settings).err().unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::InvalidSettings);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn allocate_should_report_no_space_in_occupied_range() {
+        // Our own executable's code is always mapped, so there's no free space in it.
+        let address = allocate_should_report_no_space_in_occupied_range as *const () as usize;
+        let granularity = get_sys_info().allocation_granularity as usize;
+        let mut settings = BufferAllocatorSettings {
+            min_address: round_down(address, granularity),
+            max_address: round_down(address, granularity) + granularity,
+            size: granularity as u32,
+            retry_count: 1,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let error = allocate(&mut settings).err().unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::NoSpaceInRange);
+        assert!(error.regions_examined > 0);
+        assert!(error.nearest_free_region.is_some());
+    }
+
+    #[test]
+    fn allocate_with_backend_should_skip_gaps_too_small_in_fragmented_layout() {
+        let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x10000);
+        for x in 0..8 {
+            backend.add_mapping(x * 0x40000, x * 0x40000 + 0x30000);
+        }
+
+        let mut settings = BufferAllocatorSettings {
+            max_address: 0x400000,
+            size: 0x10000,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let item = allocate_with_backend(&mut settings, &backend).unwrap();
+        let base_address = item.base_address.value;
+        assert_eq!(base_address, 0x1F0000);
+    }
+
+    #[test]
+    fn allocate_with_backend_should_fill_tight_window() {
+        let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x10000);
+        backend.add_mapping(0, 0x100000);
+        backend.add_mapping(0x120000, 0x200000);
+
+        let mut settings = BufferAllocatorSettings {
+            min_address: 0x100000,
+            max_address: 0x120000,
+            size: 0x10000,
+            placement: AllocationPlacement::Highest,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let item = allocate_with_backend(&mut settings, &backend).unwrap();
+        let base_address = item.base_address.value;
+        assert_eq!(base_address, 0x100000);
+
+        let error = allocate_with_backend(&mut settings, &backend)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::NoSpaceInRange);
+    }
+
+    #[test]
+    fn allocate_with_backend_should_use_requested_granularity() {
+        let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x1000);
+        backend.add_mapping(0, 0x101000);
+        backend.add_mapping(0x104000, 0x200000);
+
+        let mut settings = BufferAllocatorSettings {
+            min_address: 0x100000,
+            max_address: 0x110000,
+            size: 0x1000,
+            allocation_granularity: 0x10000,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let error = allocate_with_backend(&mut settings, &backend)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind, BufferAllocationErrorKind::NoSpaceInRange);
+
+        settings.size = 0x1000;
+        settings.allocation_granularity = 0x1000;
+        let item = allocate_with_backend(&mut settings, &backend).unwrap();
+        let base_address = item.base_address.value;
+        assert_eq!(base_address, 0x101000);
+    }
+
+    #[test]
+    fn allocate_with_backend_should_report_refused_mapping() {
+        let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x10000);
+        backend.refuse_mapping(0, 0x100000, 1);
+
+        let mut settings = BufferAllocatorSettings {
+            max_address: 0x80000,
+            size: 0x10000,
+            retry_count: 1,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let error = allocate_with_backend(&mut settings, &backend)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.kind,
+            BufferAllocationErrorKind::MapFailed { os_error: 1 }
+        );
+        assert_eq!(error.nearest_free_region, Some((0, 0x7FFF_FFFF)));
+    }
+
+    #[test]
+    fn allocate_with_backend_should_place_nearest_to_target() {
+        let backend = SimulatedBackend::new(0x7FFF_FFFF, 0x10000);
+        backend.add_mapping(0x480000, 0x500000);
+
+        let mut settings = BufferAllocatorSettings::from_proximity(0x100000, 0x500000, 0x10000);
+        let item = allocate_with_backend(&mut settings, &backend).unwrap();
+        let base_address = item.base_address.value;
+        assert_eq!(base_address, 0x500000);
+        assert_eq!(backend.mappings().len(), 2);
+    }
+
     // Allocation Tests
 
     #[test]
@@ -301,6 +1176,7 @@ mod tests {
             target_process_id: get_sys_info().this_process_id,
             retry_count: 8,
             brute_force: false,
+            ..BufferAllocatorSettings::new()
         };
 
         let item = allocate(&mut settings).unwrap();
@@ -319,6 +1195,7 @@ mod tests {
             target_process_id: get_sys_info().this_process_id,
             retry_count: 8,
             brute_force: false,
+            ..BufferAllocatorSettings::new()
         };
 
         let item = allocate(&mut settings).unwrap();
@@ -328,6 +1205,22 @@ mod tests {
         free(item);
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn can_allocate_with_coarse_granularity() {
+        const GRANULARITY: u32 = 0x10000;
+        let mut settings = BufferAllocatorSettings {
+            size: 4096,
+            allocation_granularity: GRANULARITY,
+            ..BufferAllocatorSettings::new()
+        };
+
+        let item = allocate(&mut settings).unwrap();
+        assert_eq!(item.base_address.value % GRANULARITY as usize, 0);
+        assert_eq!(item.size, GRANULARITY);
+        free(item);
+    }
+
     // For testing use only.
     fn free(item: LocatorItem) {
         #[cfg(target_os = "windows")]
@@ -1,77 +1,161 @@
+use crate::backends::{LinuxBackend, MemoryBackend};
+use crate::internal::buffer_allocator::allocate_in_backend;
 use crate::structs::errors::BufferAllocationError;
 use crate::structs::internal::LocatorItem;
 use crate::structs::params::BufferAllocatorSettings;
-use crate::utilities::cached::get_sys_info;
-use crate::utilities::linux_map_parser::get_free_regions_from_process_id;
-use crate::{
-    internal::buffer_allocator::get_possible_buffer_addresses,
-    utilities::map_parser_utilities::MemoryMapEntry,
-};
-use libc::{
-    mmap, munmap, MAP_ANONYMOUS, MAP_FIXED_NOREPLACE, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE,
-};
+
+#[cfg(target_arch = "x86_64")]
+use crate::structs::params::AllocationPlacement;
+
+#[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+use crate::structs::errors::BufferAllocationErrorKind;
+
+#[cfg(not(all(target_arch = "x86_64", feature = "external_processes")))]
+use crate::{structs::errors::BufferAllocationErrorKind, utilities::cached::get_sys_info};
 
 // Implementation //
 pub fn allocate_linux(
     settings: &BufferAllocatorSettings,
 ) -> Result<LocatorItem, BufferAllocationError> {
-    for _ in 0..settings.retry_count {
-        let regions = get_free_regions_from_process_id(settings.target_process_id as i32);
-        for region in regions {
-            if region.start_address > settings.max_address {
-                break;
-            }
-
-            unsafe {
-                match try_allocate_buffer(&region, settings) {
-                    Ok(item) => return Ok(item),
-                    Err(_) => continue,
-                }
-            }
+    let mut backend = create_backend(settings)?;
+    if settings.huge_pages {
+        backend = backend.with_huge_pages();
+    }
+
+    // Most requests are for the low 2GiB; let the kernel find a spot, without reading the memory map.
+    #[cfg(target_arch = "x86_64")]
+    if can_use_map_32bit(settings) {
+        if let Some(item) = try_map_32bit(&backend, settings) {
+            return Ok(item);
         }
     }
 
-    Err(BufferAllocationError::new(
+    allocate_in_backend(&backend, settings, "Failed to allocate buffer on Linux")
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+fn create_backend(
+    settings: &BufferAllocatorSettings,
+) -> Result<LinuxBackend, BufferAllocationError> {
+    LinuxBackend::for_process(settings.target_process_id).map_err(|os_error| {
+        BufferAllocationError::with_kind(
+            *settings,
+            "Failed to attach to process",
+            BufferAllocationErrorKind::ProcessUnavailable { os_error },
+        )
+    })
+}
+
+#[cfg(not(all(target_arch = "x86_64", feature = "external_processes")))]
+fn create_backend(
+    settings: &BufferAllocatorSettings,
+) -> Result<LinuxBackend, BufferAllocationError> {
+    if get_sys_info().this_process_id == settings.target_process_id {
+        return Ok(LinuxBackend::new());
+    }
+
+    Err(BufferAllocationError::with_kind(
         *settings,
-        "Failed to allocate buffer on Linux",
+        "Allocating in other processes requires the 'external_processes' feature (x86_64 only)",
+        BufferAllocationErrorKind::InvalidSettings,
     ))
 }
 
-unsafe fn try_allocate_buffer(
-    entry: &MemoryMapEntry,
+/// Range of addresses `MAP_32BIT` allocations are placed in by the kernel, on x86_64.
+#[cfg(target_arch = "x86_64")]
+const MAP_32BIT_RANGE: (usize, usize) = (0x4000_0000, 0x8000_0000);
+
+/// Returns true if any address `MAP_32BIT` can return satisfies the settings.
+#[cfg(target_arch = "x86_64")]
+fn can_use_map_32bit(settings: &BufferAllocatorSettings) -> bool {
+    // Explicit placement, or huge page alignment can't be requested from the kernel.
+    settings.placement == AllocationPlacement::Lowest
+        && !settings.huge_pages
+        && settings.min_address <= MAP_32BIT_RANGE.0
+        && settings.max_address >= MAP_32BIT_RANGE.1 - 1
+}
+
+/// Asks the kernel for memory in the low 2GiB with `MAP_32BIT`.
+#[cfg(target_arch = "x86_64")]
+fn try_map_32bit(
+    backend: &LinuxBackend,
     settings: &BufferAllocatorSettings,
-) -> Result<LocatorItem, &'static str> {
-    let buffer: &mut [usize; 4] = &mut [0; 4];
-
-    for addr in get_possible_buffer_addresses(
-        settings.min_address,
-        settings.max_address,
-        entry.start_address,
-        entry.end_address,
-        settings.size as usize,
-        get_sys_info().allocation_granularity as usize,
-        buffer,
-    ) {
-        let allocated = mmap(
-            *addr as *mut _,
-            settings.size as usize,
-            PROT_READ | PROT_WRITE | PROT_EXEC,
-            MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE,
-            -1,
-            0,
-        );
-
-        if allocated.is_null() {
-            continue;
-        }
+) -> Option<LocatorItem> {
+    let size = settings.size as usize;
+    let allocated = backend.map_32bit(size)?;
 
-        if allocated as usize != *addr {
-            munmap(allocated, settings.size as usize);
-            continue;
+    // Should never happen given the check in `can_use_map_32bit`, but the range is a kernel detail.
+    if allocated < settings.min_address
+        || allocated + size - 1 > settings.max_address
+        || allocated % settings.allocation_granularity as usize != 0
+    {
+        unsafe {
+            let _ = backend.unmap(allocated, size);
         }
+        return None;
+    }
+
+    Some(LocatorItem::new(allocated, size as u32))
+}
 
-        return Ok(LocatorItem::new(allocated as usize, settings.size));
+#[cfg(test)]
+#[cfg(target_arch = "x86_64")]
+mod tests {
+    use super::*;
+    use crate::utilities::cached::get_sys_info;
+
+    #[test]
+    fn can_use_map_32bit_when_window_covers_low_2gib() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0;
+        settings.max_address = i32::MAX as usize;
+        assert!(can_use_map_32bit(&settings));
+
+        settings.min_address = 0x1000_0000;
+        settings.max_address = get_sys_info().max_address;
+        assert!(can_use_map_32bit(&settings));
     }
 
-    Err("Failed to allocate buffer")
+    #[test]
+    fn cannot_use_map_32bit_when_window_is_partial() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0;
+        settings.max_address = 0x6000_0000;
+        assert!(!can_use_map_32bit(&settings));
+
+        settings.min_address = 0x5000_0000;
+        settings.max_address = i32::MAX as usize;
+        assert!(!can_use_map_32bit(&settings));
+    }
+
+    #[test]
+    fn cannot_use_map_32bit_with_placement_or_huge_pages() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0;
+        settings.max_address = i32::MAX as usize;
+        settings.placement = AllocationPlacement::Highest;
+        assert!(!can_use_map_32bit(&settings));
+
+        settings.placement = AllocationPlacement::Lowest;
+        settings.huge_pages = true;
+        assert!(!can_use_map_32bit(&settings));
+    }
+
+    #[test]
+    fn try_map_32bit_returns_low_memory() {
+        let mut settings = BufferAllocatorSettings::new();
+        settings.min_address = 0;
+        settings.max_address = i32::MAX as usize;
+        settings.sanitize();
+
+        let backend = LinuxBackend::new();
+        let item = try_map_32bit(&backend, &settings).unwrap();
+        assert!(item.base_address.value >= MAP_32BIT_RANGE.0);
+        assert!(item.max_address() <= MAP_32BIT_RANGE.1);
+        unsafe {
+            backend
+                .unmap(item.base_address.value, item.size as usize)
+                .unwrap();
+        }
+    }
 }
@@ -1,48 +1,63 @@
-use crate::structs::errors::BufferAllocationError;
+use crate::structs::errors::{BufferAllocationError, BufferAllocationErrorKind};
 use crate::structs::internal::LocatorItem;
-use crate::structs::params::BufferAllocatorSettings;
+use crate::structs::params::{AllocationPlacement, BufferAllocatorSettings};
 use crate::utilities::cached::get_sys_info;
 use crate::utilities::map_parser_utilities::get_free_regions;
 use crate::{
-    internal::buffer_allocator::get_possible_buffer_addresses,
+    internal::buffer_allocator::{
+        collect_free_regions, get_possible_buffer_addresses, get_preferred_buffer_addresses,
+        AllocationDiagnostics,
+    },
     utilities::map_parser_utilities::MemoryMapEntry,
 };
 use core::cmp::min;
 use core::mem;
+use errno::errno;
 use mmap_rs_with_map_from_existing::{MemoryAreas, MmapOptions, UnsafeMmapFlags};
 
 // Implementation //
 pub fn allocate_mmap_rs(
     settings: &BufferAllocatorSettings,
 ) -> Result<LocatorItem, BufferAllocationError> {
+    let mut diagnostics = AllocationDiagnostics::new();
     for _ in 0..settings.retry_count {
-        let maps = MemoryAreas::open(None).map_err(|_x| BufferAllocationError {
-            settings: *settings,
-            text: "Failed to Query Memory Pages via mmap-rs. Probably unsupported or lacking permissions.",
-        })?;
-
-        let mapped_regions: Vec<MemoryMapEntry> = maps
-            .filter(|x| x.is_ok())
-            .map(
-                |x: Result<
-                    mmap_rs_with_map_from_existing::MemoryArea,
-                    mmap_rs_with_map_from_existing::Error,
-                >| unsafe {
-                    let area = x.unwrap_unchecked();
-                    MemoryMapEntry::new(area.start(), area.end())
+        diagnostics = AllocationDiagnostics::new();
+        let maps = MemoryAreas::open(None).map_err(|_x| {
+            BufferAllocationError::with_kind(
+                *settings,
+                "Failed to Query Memory Pages via mmap-rs. Probably unsupported or lacking permissions.",
+                BufferAllocationErrorKind::ProcessUnavailable {
+                    os_error: errno().0,
                 },
             )
-            .collect();
+        })?;
+
+        // Streamed straight into the free regions, without collecting the mapped ones.
+        let mapped_regions = maps
+            .filter_map(|x| x.ok())
+            .map(|area| MemoryMapEntry::new(area.start(), area.end()));
+
+        let free_regions = collect_free_regions(settings, &mut diagnostics, |on_region| {
+            get_free_regions(mapped_regions, on_region);
+            Ok(())
+        })?;
 
-        let free_regions = get_free_regions(&mapped_regions);
+        if settings.placement != AllocationPlacement::Lowest {
+            let granularity = get_sys_info().get_allocation_granularity() as usize;
+            for addr in get_preferred_buffer_addresses(&free_regions, settings, granularity) {
+                if let Ok(item) = unsafe { try_map_at(addr, settings.size) } {
+                    return Ok(item);
+                }
+            }
+        }
 
-        for region in free_regions {
+        for region in &free_regions {
             if region.start_address > settings.max_address {
                 break;
             }
 
             unsafe {
-                match try_allocate_buffer(&region, settings) {
+                match try_allocate_buffer(region, settings) {
                     Ok(item) => return Ok(item),
                     Err(_) => continue,
                 }
@@ -50,10 +65,7 @@ pub fn allocate_mmap_rs(
         }
     }
 
-    Err(BufferAllocationError::new(
-        *settings,
-        "Failed to allocate buffer (mmap_rs)",
-    ))
+    Err(diagnostics.into_error(*settings, "Failed to allocate buffer (mmap_rs)"))
 }
 
 unsafe fn try_allocate_buffer(
@@ -71,30 +83,42 @@ unsafe fn try_allocate_buffer(
         get_sys_info().get_allocation_granularity() as usize,
         buffer,
     ) {
-        let mmapoptions = MmapOptions::new(settings.size as usize)
-            .map_err(|_x| "Failed to create mmap options")?
-            .with_address(*addr)
-            .with_unsafe_flags(UnsafeMmapFlags::MAP_FIXED)
-            .with_unsafe_flags(UnsafeMmapFlags::JIT);
-
-        let map: Result<
-            mmap_rs_with_map_from_existing::MmapMut,
-            mmap_rs_with_map_from_existing::Error,
-        > = unsafe { mmapoptions.map_exec_mut() };
-        if map.is_err() {
-            continue;
+        match try_map_at(*addr, settings.size) {
+            Ok(item) => return Ok(item),
+            Err(_) => continue,
         }
+    }
 
-        let mapped = map.unwrap();
-        let mapped_addr = mapped.start();
+    Err("Failed to allocate buffer")
+}
 
-        if mapped.start() != *addr {
-            continue; // dropped
-        }
+unsafe fn try_map_at(addr: usize, size: u32) -> Result<LocatorItem, &'static str> {
+    // Null page may be mappable with elevated privileges, but a buffer can never start at 0.
+    if addr == 0 {
+        return Err("Cannot map memory at address 0");
+    }
+
+    let mmapoptions = MmapOptions::new(size as usize)
+        .map_err(|_x| "Failed to create mmap options")?
+        .with_address(addr)
+        .with_unsafe_flags(UnsafeMmapFlags::MAP_FIXED)
+        .with_unsafe_flags(UnsafeMmapFlags::JIT);
 
-        mem::forget(mapped);
-        return Ok(LocatorItem::new(mapped_addr, settings.size));
+    let map: Result<
+        mmap_rs_with_map_from_existing::MmapMut,
+        mmap_rs_with_map_from_existing::Error,
+    > = unsafe { mmapoptions.map_exec_mut() };
+    if map.is_err() {
+        return Err("Failed to map memory");
     }
 
-    Err("Failed to allocate buffer")
+    let mapped = map.unwrap();
+    let mapped_addr = mapped.start();
+
+    if mapped.start() != addr {
+        return Err("Memory was mapped at wrong address"); // dropped
+    }
+
+    mem::forget(mapped);
+    Ok(LocatorItem::new(mapped_addr, size))
 }
@@ -1,4 +1,4 @@
-use crate::internal::buffer_allocator::get_possible_buffer_addresses;
+use crate::internal::buffer_allocator::{get_possible_buffer_addresses, AllocationDiagnostics};
 use crate::structs::errors::BufferAllocationError;
 use crate::structs::internal::LocatorItem;
 use crate::structs::params::BufferAllocatorSettings;
@@ -7,6 +7,7 @@ use crate::utilities::wrappers::Unaligned;
 use core::cmp::min;
 use core::mem;
 use libc::{mach_msg_type_number_t, mach_port_t, mach_task_self, mach_vm_size_t};
+use mach::kern_return::KERN_NO_SPACE;
 use mach::vm::{mach_vm_allocate, mach_vm_deallocate, mach_vm_protect, mach_vm_region};
 use mach::vm_prot::*;
 use mach::vm_region;
@@ -22,7 +23,9 @@ pub fn allocate_osx(
 
     unsafe {
         let self_task = mach_task_self();
+        let mut diagnostics = AllocationDiagnostics::new();
         for _ in 0..settings.retry_count {
+            diagnostics = AllocationDiagnostics::new();
             let mut count =
                 mem::size_of::<vm_region_basic_info_data_64_t>() as mach_msg_type_number_t;
             let mut object_name: mach_port_t = 0;
@@ -47,6 +50,12 @@ pub fn allocate_osx(
                 if kr == 1 {
                     let padding = max_address as usize - current_address as usize;
                     if padding > 0 {
+                        diagnostics.on_free_region(
+                            current_address as usize,
+                            max_address as usize,
+                            settings,
+                        );
+
                         let mut result_addr: usize = 0;
                         if try_allocate_buffer(
                             current_address as usize,
@@ -54,6 +63,7 @@ pub fn allocate_osx(
                             settings,
                             self_task,
                             &mut result_addr,
+                            &mut diagnostics,
                         ) {
                             return Ok(LocatorItem {
                                 base_address: Unaligned::new(result_addr),
@@ -72,6 +82,12 @@ pub fn allocate_osx(
 
                 if actual_address > current_address {
                     let free_bytes = actual_address - current_address;
+                    diagnostics.on_free_region(
+                        current_address as usize,
+                        actual_address as usize,
+                        settings,
+                    );
+
                     let mut result_addr: usize = 0;
                     if try_allocate_buffer(
                         current_address as usize,
@@ -79,6 +95,7 @@ pub fn allocate_osx(
                         settings,
                         self_task,
                         &mut result_addr,
+                        &mut diagnostics,
                     ) {
                         return Ok(LocatorItem {
                             base_address: Unaligned::new(result_addr),
@@ -93,10 +110,7 @@ pub fn allocate_osx(
             }
         }
 
-        Err(BufferAllocationError::new(
-            *settings,
-            "Failed to allocate buffer on OSX",
-        ))
+        Err(diagnostics.into_error(*settings, "Failed to allocate buffer on OSX"))
     }
 }
 
@@ -106,6 +120,7 @@ fn try_allocate_buffer(
     settings: &BufferAllocatorSettings,
     self_task: mach_port_t,
     result_addr: &mut usize,
+    diagnostics: &mut AllocationDiagnostics,
 ) -> bool {
     let mut results: [usize; 4] = [0; 4];
     let buffer_pointers = get_buffer_pointers_in_page_range(
@@ -129,6 +144,11 @@ fn try_allocate_buffer(
         };
 
         if kr != 0 {
+            // KERN_NO_SPACE: Something was allocated here since we queried the region.
+            if kr != KERN_NO_SPACE {
+                diagnostics.on_map_error(kr);
+            }
+
             continue;
         }
 
@@ -155,6 +175,7 @@ fn try_allocate_buffer(
         };
 
         if kr != 0 {
+            diagnostics.on_map_error(kr);
             unsafe {
                 mach_vm_deallocate(self_task, allocated, settings.size as mach_vm_size_t);
             }
@@ -1,6 +1,6 @@
 // Windows specific code for buffer allocator.
-use crate::internal::buffer_allocator::get_possible_buffer_addresses;
-use crate::structs::errors::BufferAllocationError;
+use crate::internal::buffer_allocator::{get_possible_buffer_addresses, AllocationDiagnostics};
+use crate::structs::errors::{BufferAllocationError, BufferAllocationErrorKind};
 use crate::structs::internal::LocatorItem;
 use crate::structs::params::BufferAllocatorSettings;
 use crate::utilities::cached::get_sys_info;
@@ -9,7 +9,9 @@ use crate::utilities::wrappers::Unaligned;
 use core::ffi::c_void;
 use core::mem::{size_of, zeroed};
 use core::sync::atomic::AtomicI32;
-use windows_sys::Win32::Foundation::{CloseHandle, BOOL, HANDLE};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, BOOL, ERROR_INVALID_ADDRESS, HANDLE,
+};
 use windows_sys::Win32::System::Memory::{
     VirtualAlloc, VirtualFree, VirtualQuery, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_FREE,
     MEM_RELEASE, MEM_RESERVE, PAGE_EXECUTE_READWRITE,
@@ -161,9 +163,12 @@ pub fn allocate_windows(
         let process_handle = ProcessHandle::open_process(settings.target_process_id);
 
         if !process_handle.is_valid() {
-            return Err(BufferAllocationError::new(
+            return Err(BufferAllocationError::with_kind(
                 *settings,
                 "Failed to open process",
+                BufferAllocationErrorKind::ProcessUnavailable {
+                    os_error: GetLastError() as i32,
+                },
             ));
         }
 
@@ -191,8 +196,10 @@ fn allocate_fast<T: Kernel32>(
 ) -> Result<LocatorItem, BufferAllocationError> {
     max_address = min(max_address, settings.max_address);
 
+    let mut diagnostics = AllocationDiagnostics::new();
     for _ in 0..settings.retry_count {
         // Until we get all of the pages.
+        diagnostics = AllocationDiagnostics::new();
         let mut current_address = settings.min_address;
         let mut memory_information: MEMORY_BASIC_INFORMATION = unsafe { zeroed() };
         while current_address <= max_address {
@@ -204,8 +211,13 @@ fn allocate_fast<T: Kernel32>(
                 break;
             }
 
+            if memory_information.State == MEM_FREE {
+                let start = memory_information.BaseAddress as usize;
+                diagnostics.on_free_region(start, start + memory_information.RegionSize, settings);
+            }
+
             // Add the page and increment address iterator to go to next page.
-            match try_allocate_buffer(k32, &mut memory_information, settings) {
+            match try_allocate_buffer(k32, &mut memory_information, settings, &mut diagnostics) {
                 Some(item) => return Ok(item),
                 None => {
                     current_address += memory_information.RegionSize;
@@ -226,7 +238,7 @@ fn allocate_fast<T: Kernel32>(
                 break;
             }
 
-            match try_allocate_buffer(k32, &mut memory_information, settings) {
+            match try_allocate_buffer(k32, &mut memory_information, settings, &mut diagnostics) {
                 Some(item) => return Ok(item),
                 None => {
                     current_address += get_sys_info().allocation_granularity as usize;
@@ -235,16 +247,14 @@ fn allocate_fast<T: Kernel32>(
         }
     }
 
-    Err(BufferAllocationError::new(
-        *settings,
-        "Failed to allocate buffer on Windows",
-    ))
+    Err(diagnostics.into_error(*settings, "Failed to allocate buffer on Windows"))
 }
 
 fn try_allocate_buffer<T: Kernel32>(
     k32: &T,
     page_info: &mut MEMORY_BASIC_INFORMATION,
     settings: &BufferAllocatorSettings,
+    diagnostics: &mut AllocationDiagnostics,
 ) -> Option<LocatorItem> {
     // Fast return if page is not free.
     if page_info.State != MEM_FREE {
@@ -262,6 +272,12 @@ fn try_allocate_buffer<T: Kernel32>(
         let allocated = k32.virtual_alloc(*addr as *const c_void, settings.size as usize);
 
         if allocated.is_null() {
+            // ERROR_INVALID_ADDRESS: Something was allocated here since we queried the page.
+            let error = unsafe { GetLastError() };
+            if error != ERROR_INVALID_ADDRESS {
+                diagnostics.on_map_error(error as i32);
+            }
+
             continue;
         }
 
@@ -1,4 +1,3 @@
-extern crate alloc;
 use core::ptr::null_mut;
 
 use crate::structs::internal::LocatorHeader;
@@ -7,27 +6,21 @@ use crate::utilities::cached::get_sys_info;
 use spin::Mutex;
 
 #[cfg(not(feature = "all_private"))]
-use {
-    crate::internal::memory_mapped_file::MemoryMappedFile, alloc::boxed::Box, alloc::string::String,
+use crate::{
+    internal::memory_mapped_file::{MemoryMappedFile, PlatformMemoryMappedFile},
+    utilities::stack_c_string::StackCString,
 };
 
 #[cfg(unix)]
 #[cfg(not(feature = "all_private"))]
-use {
-    super::memory_mapped_file_unix::BASE_DIR,
-    crate::internal::memory_mapped_file_unix::UnixMemoryMappedFile, errno::errno, libc::kill,
-};
-
-#[cfg(target_os = "windows")]
-#[cfg(not(feature = "all_private"))]
-use crate::internal::memory_mapped_file_windows::WindowsMemoryMappedFile;
+use {super::memory_mapped_file_unix::BASE_DIR, errno::errno, libc::kill};
 
 pub struct LocatorHeaderFinder {}
 
 static mut LOCATOR_HEADER_ADDRESS: *mut LocatorHeader = null_mut();
 
 #[cfg(not(feature = "all_private"))]
-static mut MMF: Option<Box<dyn MemoryMappedFile>> = None;
+static mut MMF: Option<PlatformMemoryMappedFile> = None;
 
 static GLOBAL_LOCK: Mutex<()> = Mutex::new(());
 
@@ -57,24 +50,21 @@ impl LocatorHeaderFinder {
 
     #[cfg_attr(feature = "size_opt", optimize(size))]
     #[cfg(not(feature = "all_private"))]
-    fn open_or_create_memory_mapped_file() -> Box<dyn MemoryMappedFile> {
-        // no_std
-        let mut name = String::from("/Reloaded.Memory.Buffers.MemoryBuffer, PID ");
+    fn open_or_create_memory_mapped_file() -> PlatformMemoryMappedFile {
         let sys_info = get_sys_info();
+        let name = Self::get_memory_mapped_file_name(sys_info.this_process_id);
+        PlatformMemoryMappedFile::new(name.as_str(), sys_info.allocation_granularity as usize)
+    }
+
+    /// Returns the name of the memory mapped file holding the first locator of the given process.
+    #[cfg(not(feature = "all_private"))]
+    pub(crate) fn get_memory_mapped_file_name(process_id: u32) -> StackCString {
         let mut buffer = itoa::Buffer::new();
-        name.push_str(buffer.format(sys_info.this_process_id));
-
-        #[cfg(target_os = "windows")]
-        return Box::new(WindowsMemoryMappedFile::new(
-            &name,
-            sys_info.allocation_granularity as usize,
-        ));
-
-        #[cfg(unix)]
-        return Box::new(UnixMemoryMappedFile::new(
-            &name,
-            sys_info.allocation_granularity as usize,
-        ));
+        StackCString::new(&[
+            "/Reloaded.Memory.Buffers.MemoryBuffer, PID ",
+            buffer.format(process_id),
+        ])
+        .unwrap()
     }
 
     #[cfg(test)]
@@ -95,13 +85,12 @@ impl LocatorHeaderFinder {
     #[cfg(unix)]
     #[cfg(not(feature = "all_private"))]
     fn cleanup() {
-        use alloc::ffi::CString;
         use core::ffi::CStr;
         use libc::{opendir, readdir};
 
         const MEMORY_MAPPED_FILE_PREFIX: &str = "Reloaded.Memory.Buffers.MemoryBuffer, PID ";
 
-        let c_mmf_directory = CString::new(BASE_DIR).expect("CString::new failed");
+        let c_mmf_directory = StackCString::new(&[BASE_DIR]).unwrap();
         let dir = unsafe { opendir(c_mmf_directory.as_ptr()) };
 
         if dir.is_null() {
@@ -159,7 +148,7 @@ unsafe fn init_locatorheader_standard() -> *mut LocatorHeader {
     // If the MMF previously existed, we need to read the real address from
     // the header, then close our mapping.
     if mmf.already_existed() {
-        let header_addr = (*mmf).data() as *mut LocatorHeader;
+        let header_addr = mmf.data() as *mut LocatorHeader;
         LOCATOR_HEADER_ADDRESS = (*header_addr).this_address.value;
 
         #[cfg(test)]
@@ -193,6 +182,7 @@ unsafe fn init_locatorheader_memorymappedfiles_unsupported() -> *mut LocatorHead
         target_process_id: sys_info.this_process_id,
         retry_count: 8,
         brute_force: true,
+        ..BufferAllocatorSettings::new()
     };
 
     // This call is slow but saves on code space. Also handles case of
@@ -233,7 +223,8 @@ mod tests {
             let _map = LocatorHeaderFinder::open_or_create_memory_mapped_file();
 
             let _unused = LocatorHeaderFinder::find();
-            assert_eq!(LAST_FIND_REASON, FindReason::PreviouslyExisted);
+            let reason = LAST_FIND_REASON;
+            assert_eq!(reason, FindReason::PreviouslyExisted);
         }
     }
 
@@ -245,7 +236,8 @@ mod tests {
             let address = LocatorHeaderFinder::find();
             assert!(!address.is_null());
 
-            assert_eq!(LAST_FIND_REASON, FindReason::Created);
+            let reason = LAST_FIND_REASON;
+            assert_eq!(reason, FindReason::Created);
         }
     }
 
@@ -0,0 +1,568 @@
+extern crate alloc;
+
+use crate::structs::internal::{LocatorHeader, LocatorItem};
+use crate::structs::SafeLocatorItem;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+#[cfg(target_os = "windows")]
+use {
+    crate::internal::buffer_allocator_windows::{Kernel32, LocalKernel32},
+    core::mem::zeroed,
+    windows_sys::Win32::System::Memory::MEMORY_BASIC_INFORMATION,
+};
+
+/// An item of the locator chain, cached in the index.
+struct IndexedItem {
+    /// Base address of the item; this never changes once the item is allocated.
+    base_address: usize,
+    item: *mut LocatorItem,
+}
+
+/// A locator in the chain, alongside the number of its items which have been indexed.
+struct IndexedLocator {
+    locator: *mut LocatorHeader,
+    num_indexed: u8,
+}
+
+/// A run of adjacent items in the chain, handed out as a single larger item.
+struct MergedRun {
+    /// Process local item covering the whole run; this is the item the caller locks and writes to.
+    item: Box<LocatorItem>,
+
+    /// Items of the run in the chain, which stay locked until the merged item is released.
+    items: Vec<*mut LocatorItem>,
+}
+
+/// Process local index of the items in a locator chain; each buffer manager has one.
+///
+/// # Remarks
+///
+/// The locator chain remains the source of truth; this index only caches where the items
+/// are, sorted by their base address. This allows for finding items in an address range in
+/// logarithmic time, rather than walking every item of every locator.
+///
+/// Items are only ever added to the chain (never removed or moved), so the index is kept in sync
+/// by indexing any items added since the last lookup.
+pub(crate) struct LocatorIndex {
+    /// Items, sorted by base address.
+    items: Vec<IndexedItem>,
+
+    /// Locators in the chain, in chain order.
+    locators: Vec<IndexedLocator>,
+
+    /// Runs of items currently handed out as one item.
+    merged: Vec<MergedRun>,
+}
+
+// The index only holds addresses of items in the shared locator, which are never freed.
+unsafe impl Send for LocatorIndex {}
+
+impl LocatorIndex {
+    pub(crate) const fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            locators: Vec::new(),
+            merged: Vec::new(),
+        }
+    }
+
+    /// Indexes any items which were added to the locator chain since the last call.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_locator` - The first locator in the chain.
+    ///
+    /// # Safety
+    ///
+    /// `first_locator` must point to a valid, initialized locator chain.
+    pub(crate) unsafe fn sync(&mut self, first_locator: *mut LocatorHeader) {
+        let mut locator = first_locator;
+        let mut locator_index = 0;
+        while !locator.is_null() {
+            let num_items = (*locator).num_items;
+            match self.locators.get(locator_index) {
+                Some(x) if x.locator != locator || x.num_indexed > num_items => {
+                    // Different chain (e.g. locator was reset), start from scratch.
+                    self.items.clear();
+                    self.locators.clear();
+                    return self.sync(first_locator);
+                }
+                Some(_) => {}
+                None => self.locators.push(IndexedLocator {
+                    locator,
+                    num_indexed: 0,
+                }),
+            }
+
+            let mut num_indexed = self.locators[locator_index].num_indexed;
+            while num_indexed < num_items {
+                let item = (*locator).get_item(num_indexed as usize);
+
+                // Item is still being written by another thread, pick it up next time.
+                let base_address = (*item).base_address.value;
+                if base_address == 0 {
+                    break;
+                }
+
+                let insert_at = self
+                    .items
+                    .partition_point(|x| x.base_address < base_address);
+                self.items
+                    .insert(insert_at, IndexedItem { base_address, item });
+                num_indexed += 1;
+            }
+
+            self.locators[locator_index].num_indexed = num_indexed;
+            locator = (*locator).next_locator_ptr.value;
+            locator_index += 1;
+        }
+    }
+
+    /// Gets the first available item with a lock, across the whole chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Required size of the buffer.
+    /// * `min_address` - Minimum address for the allocation.
+    /// * `max_address` - Maximum address for the allocation.
+    ///
+    /// # Returns
+    ///
+    /// Returns a locked locator item. See [`LocatorHeader::get_first_available_item_locked`].
+    ///
+    /// # Safety
+    ///
+    /// The indexed locator chain must still be valid.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub(crate) unsafe fn get_first_available_item_locked(
+        &mut self,
+        size: u32,
+        min_address: usize,
+        max_address: usize,
+    ) -> Option<SafeLocatorItem<'static>> {
+        self.release_merged_items();
+        let first_after_min = self.items.partition_point(|x| x.base_address < min_address);
+
+        // Items don't overlap, so out of all items starting before `min_address`, only the last one
+        // which still owns memory may extend (and have its write position) past `min_address`.
+        if let Some(entry) = self.items[..first_after_min]
+            .iter()
+            .rev()
+            .find(|x| (*x.item).size != 0)
+        {
+            if let Some(item) = Self::try_use_item(entry.item, size, min_address, max_address) {
+                return Some(item);
+            }
+        }
+
+        for entry in &self.items[first_after_min..] {
+            if entry.base_address >= max_address {
+                break;
+            }
+
+            if let Some(item) = Self::try_use_item(entry.item, size, min_address, max_address) {
+                return Some(item);
+            }
+        }
+
+        self.try_merge_items_locked(size, min_address, max_address)
+    }
+
+    /// Tries to merge a run of untouched, physically adjacent items into a single locked item
+    /// of at least `size` bytes.
+    ///
+    /// # Remarks
+    ///
+    /// The merge only exists in this process; the items of the run are locked, and a process local
+    /// item covering all of them is handed out instead. The items in the chain keep their size, so
+    /// every implementation sharing the chain sees them as they are.
+    ///
+    /// Once the merged item is unlocked, the next lookup writes back how much of each item was used
+    /// and unlocks them; see [`LocatorIndex::release_merged_items`].
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    unsafe fn try_merge_items_locked(
+        &mut self,
+        size: u32,
+        min_address: usize,
+        max_address: usize,
+    ) -> Option<SafeLocatorItem<'static>> {
+        let first_after_min = self.items.partition_point(|x| x.base_address < min_address);
+
+        for start in first_after_min..self.items.len() {
+            if self.items[start].base_address >= max_address {
+                break;
+            }
+
+            if let Some(mut run) = Self::try_lock_run(&self.items[start..], size, max_address) {
+                let item: *mut LocatorItem = &mut *run.item as *mut _;
+                self.merged.push(run);
+                return Some(SafeLocatorItem::new(item));
+            }
+        }
+
+        None
+    }
+
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    unsafe fn try_lock_run(
+        entries: &[IndexedItem],
+        size: u32,
+        max_address: usize,
+    ) -> Option<MergedRun> {
+        let run_length = Self::get_merge_run_length(entries, size, max_address)?;
+        let run = &entries[..run_length];
+
+        // Lock the whole run, then make sure nothing changed before we got the locks.
+        let mut num_locked = 0;
+        for entry in run {
+            if !(*entry.item).try_lock() {
+                break;
+            }
+
+            num_locked += 1;
+        }
+
+        if num_locked != run_length
+            || Self::get_merge_run_length(run, size, max_address) != Some(run_length)
+        {
+            for entry in &run[..num_locked] {
+                (*entry.item).unlock();
+            }
+
+            return None;
+        }
+
+        let total_size: u32 = run.iter().map(|x| (*x.item).size).sum();
+        let mut item = Box::new(LocatorItem::new(run[0].base_address, total_size));
+        item.lock();
+
+        Some(MergedRun {
+            item,
+            items: run.iter().map(|x| x.item).collect(),
+        })
+    }
+
+    /// Unlocks the items of every merged run whose merged item is no longer in use, after
+    /// advancing the position of each item by the amount of it that was written to.
+    unsafe fn release_merged_items(&mut self) {
+        self.merged.retain(|run| {
+            if run.item.is_taken() {
+                return true;
+            }
+
+            let mut used = run.item.position;
+            for item in &run.items {
+                let item = &mut **item;
+                item.position = used.min(item.size);
+                used -= item.position;
+                item.unlock();
+            }
+
+            false
+        });
+    }
+
+    /// Returns the number of items from the start of `entries` which must be merged to get an item
+    /// of at least `size` bytes, or `None` if the items can't be merged into one that big.
+    unsafe fn get_merge_run_length(
+        entries: &[IndexedItem],
+        size: u32,
+        max_address: usize,
+    ) -> Option<usize> {
+        let base_address = entries[0].base_address;
+        let mut end_address = base_address;
+        for (index, entry) in entries.iter().enumerate() {
+            let item = &*entry.item;
+            if entry.base_address != end_address
+                || item.size == 0
+                || item.position != 0
+                || !is_same_allocation(base_address, entry.base_address)
+            {
+                return None;
+            }
+
+            end_address += item.size as usize;
+            if end_address > max_address || end_address - base_address > u32::MAX as usize {
+                return None;
+            }
+
+            if end_address - base_address >= size as usize {
+                // A single item would have been picked up without merging.
+                return if index > 0 { Some(index + 1) } else { None };
+            }
+        }
+
+        None
+    }
+
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    unsafe fn try_use_item(
+        item: *mut LocatorItem,
+        size: u32,
+        min_address: usize,
+        max_address: usize,
+    ) -> Option<SafeLocatorItem<'static>> {
+        let item_ref = &mut *item;
+        if !item_ref.can_use(size, min_address, max_address) || !item_ref.try_lock() {
+            return None;
+        }
+
+        // Item may have been used before we got the lock.
+        if !item_ref.can_use(size, min_address, max_address) {
+            item_ref.unlock();
+            return None;
+        }
+
+        Some(SafeLocatorItem::new(item))
+    }
+}
+
+/// Returns true if both addresses belong to the same allocation, i.e. memory between them can be
+/// treated as one region (e.g. when changing page protection).
+#[cfg(target_os = "windows")]
+fn is_same_allocation(first: usize, second: usize) -> bool {
+    let kernel32 = LocalKernel32 {};
+    let mut first_info: MEMORY_BASIC_INFORMATION = unsafe { zeroed() };
+    let mut second_info: MEMORY_BASIC_INFORMATION = unsafe { zeroed() };
+    kernel32.virtual_query(first as *const _, &mut first_info) != 0
+        && kernel32.virtual_query(second as *const _, &mut second_info) != 0
+        && first_info.AllocationBase == second_info.AllocationBase
+}
+
+/// Returns true if both addresses belong to the same allocation, i.e. memory between them can be
+/// treated as one region (e.g. when changing page protection).
+///
+/// Adjacent mappings can always be treated as one region on this platform.
+#[cfg(not(target_os = "windows"))]
+fn is_same_allocation(_first: usize, _second: usize) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::internal::locator_header::LENGTH;
+    use crate::utilities::wrappers::Unaligned;
+
+    /// Makes a locator in the given buffer, with items at the given (base, size) pairs.
+    unsafe fn make_locator(
+        buffer: &mut [u8; LENGTH],
+        items: &[(usize, u32)],
+    ) -> *mut LocatorHeader {
+        let header = buffer.as_mut_ptr() as *mut LocatorHeader;
+        (*header).this_address = Unaligned::new(header);
+        (*header).next_locator_ptr = Unaligned::new(core::ptr::null_mut());
+        (*header).num_items = items.len() as u8;
+
+        for (index, (base_address, size)) in items.iter().enumerate() {
+            *(*header).get_item(index) = LocatorItem::new(*base_address, *size);
+        }
+
+        header
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_find_item_across_locators() {
+        unsafe {
+            // Arrange
+            let mut first_buf = [0u8; LENGTH];
+            let mut second_buf = [0u8; LENGTH];
+            let first = make_locator(&mut first_buf, &[(5000, 100), (1000, 100)]);
+            let second = make_locator(&mut second_buf, &[(3000, 100)]);
+            (*first).next_locator_ptr = Unaligned::new(second);
+
+            let mut index = LocatorIndex::new();
+            index.sync(first);
+
+            // Act
+            let result = index.get_first_available_item_locked(50, 2000, 4000);
+
+            // Assert
+            let item = result.unwrap();
+            let base_address = (*item.item.get()).base_address.value;
+            assert_eq!(base_address, 3000);
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_find_item_starting_before_min_address() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (900, 100)]);
+            (*(*locator).get_item(0)).position = 50;
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+
+            // Act
+            let result = index.get_first_available_item_locked(50, 1050, 1100);
+
+            // Assert
+            let item = result.unwrap();
+            let base_address = (*item.item.get()).base_address.value;
+            assert_eq!(base_address, 1000);
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_return_none_when_no_item_in_range() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (5000, 100)]);
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+
+            // Act
+            let result = index.get_first_available_item_locked(50, 2000, 4000);
+
+            // Assert
+            assert!(result.is_none());
+        }
+    }
+
+    #[test]
+    fn sync_should_index_items_added_after_previous_sync() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100)]);
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+            assert!(index
+                .get_first_available_item_locked(50, 2000, 4000)
+                .is_none());
+
+            *(*locator).get_item(1) = LocatorItem::new(3000, 100);
+            (*locator).num_items = 2;
+
+            // Act
+            index.sync(locator);
+
+            // Assert
+            assert_eq!(index.items.len(), 2);
+            assert!(index
+                .get_first_available_item_locked(50, 2000, 4000)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn sync_should_rebuild_index_when_chain_changes() {
+        unsafe {
+            // Arrange
+            let mut first_buf = [0u8; LENGTH];
+            let mut second_buf = [0u8; LENGTH];
+            let first = make_locator(&mut first_buf, &[(1000, 100), (3000, 100)]);
+            let second = make_locator(&mut second_buf, &[(5000, 100)]);
+
+            let mut index = LocatorIndex::new();
+            index.sync(first);
+
+            // Act
+            index.sync(second);
+
+            // Assert
+            assert_eq!(index.items.len(), 1);
+            assert!(index.get_first_available_item_locked(50, 0, 4000).is_none());
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_merge_adjacent_untouched_items() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (1100, 100), (1200, 100)]);
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+
+            // Act
+            let result = index.get_first_available_item_locked(150, 0, 4000);
+
+            // Assert
+            let item = result.unwrap();
+            let base_address = (*item.item.get()).base_address.value;
+            assert_eq!(base_address, 1000);
+            assert_eq!((*item.item.get()).size, 200);
+            assert_eq!((*(*locator).get_item(0)).size, 100);
+            assert_eq!((*(*locator).get_item(1)).size, 100);
+            assert!((*(*locator).get_item(1)).is_taken());
+            assert!(!(*(*locator).get_item(2)).is_taken());
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_not_merge_used_items() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (1100, 100), (1200, 100)]);
+            (*(*locator).get_item(1)).position = 1;
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+
+            // Act
+            let result = index.get_first_available_item_locked(150, 0, 4000);
+
+            // Assert
+            assert!(result.is_none());
+            assert_eq!((*(*locator).get_item(0)).size, 100);
+            assert!(!(*(*locator).get_item(0)).is_taken());
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_not_merge_locked_items() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (1100, 100)]);
+            (*(*locator).get_item(1)).lock();
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+
+            // Act
+            let result = index.get_first_available_item_locked(150, 0, 4000);
+
+            // Assert
+            assert!(result.is_none());
+            assert_eq!((*(*locator).get_item(0)).size, 100);
+            assert!(!(*(*locator).get_item(0)).is_taken());
+        }
+    }
+
+    #[test]
+    fn get_first_available_item_locked_should_write_back_merged_items_once_released() {
+        unsafe {
+            // Arrange
+            let mut buf = [0u8; LENGTH];
+            let locator = make_locator(&mut buf, &[(1000, 100), (1100, 100)]);
+
+            let mut index = LocatorIndex::new();
+            index.sync(locator);
+            let merged = index.get_first_available_item_locked(150, 0, 4000).unwrap();
+            (*merged.item.get()).position = 120;
+            drop(merged);
+
+            // Act
+            let result = index.get_first_available_item_locked(50, 0, 4000);
+
+            // Assert
+            let item = result.unwrap();
+            let base_address = (*item.item.get()).base_address.value;
+            assert_eq!(base_address, 1100);
+            assert_eq!((*(*locator).get_item(0)).position, 100);
+            assert_eq!((*(*locator).get_item(1)).position, 20);
+            assert!(!(*(*locator).get_item(0)).is_taken());
+            assert!(index.merged.is_empty());
+        }
+    }
+}
@@ -0,0 +1,205 @@
+extern crate alloc;
+use crate::structs::internal::locator_header::{LENGTH, MAX_ITEM_COUNT};
+use crate::structs::internal::{LocatorHeader, LocatorItem};
+use crate::structs::BufferSnapshot;
+use core::cmp::min;
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, read_unaligned};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(all(target_os = "linux", not(feature = "all_private")))]
+use {
+    crate::internal::locator_header_finder::LocatorHeaderFinder,
+    crate::internal::memory_mapped_file_unix::BASE_DIR,
+    crate::structs::errors::ProcessInspectionError,
+    crate::utilities::linux_process_memory::read_process_memory,
+    crate::utilities::stack_c_string::StackCString, core::ffi::c_void, errno::errno, libc::close,
+    libc::open, libc::read, libc::O_RDONLY,
+};
+
+/// Takes snapshots of all buffers in a chain of locators, without taking any locks.
+///
+/// # Arguments
+///
+/// * `first_locator` - Address of the first locator in the chain.
+/// * `read_locator` - Reads `LENGTH` bytes of the locator at the given address into the buffer,
+///   returning the `errno` on failure.
+///
+/// # Remarks
+///
+/// Items which were never allocated are skipped.
+pub(crate) fn collect_snapshots<F>(
+    first_locator: usize,
+    mut read_locator: F,
+) -> Result<Vec<BufferSnapshot>, i32>
+where
+    F: FnMut(usize, &mut [u8]) -> Result<(), i32>,
+{
+    let mut snapshots = Vec::new();
+    let mut visited = Vec::new();
+    let mut bytes = vec![0u8; LENGTH];
+    let mut locator = first_locator;
+
+    // The chain may be modified while we're reading it; guard against following a stale pointer in circles.
+    while locator != 0 && !visited.contains(&locator) {
+        visited.push(locator);
+        read_locator(locator, &mut bytes)?;
+
+        unsafe {
+            let header = read_unaligned(bytes.as_ptr() as *const LocatorHeader);
+            let num_items = min(header.num_items as u32, MAX_ITEM_COUNT) as usize;
+            for index in 0..num_items {
+                let offset = size_of::<LocatorHeader>() + index * size_of::<LocatorItem>();
+                let item = read_unaligned(bytes.as_ptr().add(offset) as *const LocatorItem);
+                if item.is_allocated() {
+                    snapshots.push(BufferSnapshot::from(&item));
+                }
+            }
+
+            locator = header.next_locator_ptr.value as usize;
+        }
+    }
+
+    Ok(snapshots)
+}
+
+/// Takes snapshots of all buffers in the current process, starting at the given locator.
+pub(crate) fn get_snapshots(first_locator: *mut LocatorHeader) -> Vec<BufferSnapshot> {
+    let result = collect_snapshots(first_locator as usize, |address, buffer| unsafe {
+        copy_nonoverlapping(address as *const u8, buffer.as_mut_ptr(), buffer.len());
+        Ok(())
+    });
+
+    // Reading our own memory can't fail.
+    result.unwrap_or_default()
+}
+
+/// Takes snapshots of all buffers in another process.
+///
+/// # Remarks
+///
+/// The address of the first locator is read from the memory mapped file of the process, then the
+/// chain is followed with `process_vm_readv`. Nothing is locked or written. The other process must
+/// have the same pointer size as the current one.
+#[cfg(all(target_os = "linux", not(feature = "all_private")))]
+pub(crate) fn get_snapshots_from_process(
+    process_id: u32,
+) -> Result<Vec<BufferSnapshot>, ProcessInspectionError> {
+    let first_locator = unsafe { read_first_locator_address(process_id) }.map_err(|os_error| {
+        ProcessInspectionError::new(process_id, "Failed to read locator file", os_error)
+    })?;
+
+    collect_snapshots(first_locator, |address, buffer| {
+        read_process_memory(process_id as i32, address, buffer)
+    })
+    .map_err(|os_error| {
+        ProcessInspectionError::new(process_id, "Failed to read locator memory", os_error)
+    })
+}
+
+/// Reads the address of the first locator of a process from its memory mapped file.
+#[cfg(all(target_os = "linux", not(feature = "all_private")))]
+unsafe fn read_first_locator_address(process_id: u32) -> Result<usize, i32> {
+    let name = LocatorHeaderFinder::get_memory_mapped_file_name(process_id);
+    let path = StackCString::new(&[BASE_DIR, name.as_str()]).ok_or(libc::EINVAL)?;
+    let fd = open(path.as_ptr(), O_RDONLY);
+    if fd == -1 {
+        return Err(errno().0);
+    }
+
+    let mut header = [0u8; size_of::<LocatorHeader>()];
+    let bytes_read = read(fd, header.as_mut_ptr() as *mut c_void, header.len());
+    let error = errno().0;
+    close(fd);
+
+    if bytes_read != header.len() as isize {
+        return Err(if bytes_read == -1 { error } else { libc::EIO });
+    }
+
+    let header = read_unaligned(header.as_ptr() as *const LocatorHeader);
+    match header.this_address.value as usize {
+        0 => Err(libc::EIO),
+        address => Ok(address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::Buffers;
+    use crate::internal::locator_header_finder::LocatorHeaderFinder;
+    use crate::structs::params::BufferSearchSettings;
+    use crate::utilities::cached::get_sys_info;
+
+    #[test]
+    fn get_snapshots_should_include_taken_buffer() {
+        let settings = BufferSearchSettings {
+            min_address: 0,
+            max_address: get_sys_info().max_address,
+            size: 4096,
+            ..BufferSearchSettings::new()
+        };
+
+        let item = Buffers::get_buffer(&settings).unwrap();
+        let base_address = unsafe { (*item.item.get()).base_address.value };
+
+        let snapshots = unsafe { get_snapshots(LocatorHeaderFinder::find()) };
+        let snapshot = snapshots
+            .iter()
+            .find(|x| x.base_address == base_address)
+            .unwrap();
+
+        assert!(snapshot.is_taken);
+        assert!(snapshot.bytes_left() >= 4096);
+    }
+
+    #[test]
+    fn collect_snapshots_should_stop_on_cycle() {
+        let mut header = LocatorHeader::new();
+        let address = &mut header as *mut LocatorHeader as usize;
+        header.next_locator_ptr.value = address as *mut LocatorHeader;
+
+        let mut reads = 0;
+        let result = collect_snapshots(address, |_, buffer| {
+            reads += 1;
+            unsafe {
+                copy_nonoverlapping(
+                    &header as *const LocatorHeader as *const u8,
+                    buffer.as_mut_ptr(),
+                    size_of::<LocatorHeader>(),
+                );
+            }
+            Ok(())
+        });
+
+        assert_eq!(result.unwrap().len(), 0);
+        assert_eq!(reads, 1);
+    }
+
+    #[test]
+    fn collect_snapshots_should_return_read_error() {
+        let result = collect_snapshots(0x1000, |_, _| Err(libc::EFAULT));
+        assert_eq!(result, Err(libc::EFAULT));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", not(feature = "all_private")))]
+    fn get_snapshots_from_process_should_match_local_snapshots() {
+        unsafe {
+            let first_locator = LocatorHeaderFinder::find();
+            let local = get_snapshots(first_locator);
+            let remote = get_snapshots_from_process(get_sys_info().this_process_id).unwrap();
+            assert_eq!(local, remote);
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", not(feature = "all_private")))]
+    fn get_snapshots_from_process_should_fail_without_locator() {
+        let error = get_snapshots_from_process(u32::MAX).unwrap_err();
+        assert_eq!(error.process_id, u32::MAX);
+        assert_eq!(error.os_error, libc::ENOENT);
+    }
+}
@@ -3,3 +3,11 @@ pub trait MemoryMappedFile {
     unsafe fn data(&self) -> *mut u8;
     fn length(&self) -> usize;
 }
+
+/// The memory mapped file implementation of the current platform.
+#[cfg(unix)]
+pub type PlatformMemoryMappedFile = super::memory_mapped_file_unix::UnixMemoryMappedFile;
+
+/// The memory mapped file implementation of the current platform.
+#[cfg(target_os = "windows")]
+pub type PlatformMemoryMappedFile = super::memory_mapped_file_windows::WindowsMemoryMappedFile;
@@ -1,16 +1,9 @@
-extern crate alloc;
-
-use alloc::ffi::CString;
 use core::mem::MaybeUninit;
 use core::ptr::null_mut;
 
-#[cfg(not(feature = "std"))]
-use alloc::string::String;
-
 #[cfg(not(feature = "no_format"))]
 use errno::errno;
 
-use libc::c_char;
 use libc::mkdir;
 use libc::stat;
 use libc::S_IFDIR;
@@ -23,6 +16,7 @@ use libc::{
 use libc::c_uint;
 
 use crate::internal::memory_mapped_file::MemoryMappedFile;
+use crate::utilities::stack_c_string::StackCString;
 
 #[cfg(not(target_os = "android"))]
 pub const BASE_DIR: &str = "/tmp/.reloaded/memory.buffers";
@@ -35,17 +29,12 @@ pub struct UnixMemoryMappedFile {
     pub already_existed: bool,
     pub data: *mut u8,
     pub length: usize,
-    pub file_path: String,
+    pub file_path: StackCString,
 }
 
 impl UnixMemoryMappedFile {
     pub fn new(name: &str, length: usize) -> UnixMemoryMappedFile {
-        let mut new_name = String::with_capacity(BASE_DIR.len() + name.len());
-        new_name.push_str(BASE_DIR);
-        new_name.push_str(name);
-
-        let file_name = CString::new(new_name.as_str()).expect("CString::new failed");
-
+        let file_name = StackCString::new(&[BASE_DIR, name]).expect("Invalid file name");
         let mut file_descriptor = unsafe { open(file_name.as_ptr(), O_RDWR) };
         let already_existed = file_descriptor != -1;
 
@@ -56,10 +45,10 @@ impl UnixMemoryMappedFile {
             }
 
             #[cfg(not(any(target_os = "macos", target_os = "ios")))]
-            Self::open_unix(file_name, &mut file_descriptor);
+            Self::open_unix(&file_name, &mut file_descriptor);
 
             #[cfg(any(target_os = "macos", target_os = "ios"))]
-            Self::open_macos(file_name, &mut file_descriptor);
+            Self::open_macos(&file_name, &mut file_descriptor);
 
             if file_descriptor == -1 {
                 #[cfg(feature = "no_format")]
@@ -100,38 +89,36 @@ impl UnixMemoryMappedFile {
             already_existed,
             data: data as *mut u8,
             length,
-            file_path: new_name,
+            file_path: file_name,
         }
     }
 
     #[cfg(any(target_os = "macos", target_os = "ios"))]
-    fn open_macos(file_name: CString, x: &mut c_int) {
+    fn open_macos(file_name: &StackCString, x: &mut c_int) {
         unsafe { *x = open(file_name.as_ptr(), O_RDWR | O_CREAT, S_IRWXU as c_uint) }
     }
 
     #[cfg(unix)]
     #[cfg(not(any(target_os = "macos", target_os = "ios")))]
-    fn open_unix(file_name: CString, x: &mut c_int) {
+    fn open_unix(file_name: &StackCString, x: &mut c_int) {
         unsafe { *x = open(file_name.as_ptr(), O_RDWR | O_CREAT, S_IRWXU) }
     }
 
     unsafe fn create_dir_all(path: &str) {
-        let mut current_path = String::with_capacity(path.len());
-        current_path.push('/');
+        let mut current_path = StackCString::new(&["/"]).unwrap();
         for component in path.split('/') {
             if !component.is_empty() {
-                current_path.push_str(component);
-
-                // Convert current_path to C string
-                let c_path = CString::new(current_path.as_str()).unwrap();
+                if !current_path.push_str(component) {
+                    break;
+                }
 
                 // Properly handle MaybeUninit
                 let mut stat_buf = MaybeUninit::uninit();
-                let stat_result = stat(c_path.as_ptr(), stat_buf.as_mut_ptr());
+                let stat_result = stat(current_path.as_ptr(), stat_buf.as_mut_ptr());
 
                 if stat_result != 0 {
                     // stat failed, directory does not exist, try to create it
-                    if mkdir(c_path.as_ptr(), S_IRWXU) != 0 {
+                    if mkdir(current_path.as_ptr(), S_IRWXU) != 0 {
                         // Handle error or break as needed
                         break;
                     }
@@ -144,7 +131,7 @@ impl UnixMemoryMappedFile {
                     }
                 }
 
-                current_path.push('/');
+                current_path.push_str("/");
             }
         }
     }
@@ -157,7 +144,7 @@ impl Drop for UnixMemoryMappedFile {
         unsafe { close(self.file_descriptor) };
         if !self.already_existed {
             unsafe {
-                libc::unlink(self.file_path.as_ptr() as *const c_char);
+                libc::unlink(self.file_path.as_ptr());
             }
         }
     }
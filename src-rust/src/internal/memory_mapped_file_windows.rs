@@ -1,9 +1,7 @@
-extern crate alloc;
-
 use core::ffi::c_void;
 
 use crate::internal::memory_mapped_file::MemoryMappedFile;
-use alloc::ffi::CString;
+use crate::utilities::stack_c_string::StackCString;
 use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
 use windows_sys::Win32::Security::SECURITY_ATTRIBUTES;
 use windows_sys::Win32::System::Memory::{
@@ -20,7 +18,7 @@ pub struct WindowsMemoryMappedFile {
 
 impl WindowsMemoryMappedFile {
     pub fn new(name: &str, length: usize) -> WindowsMemoryMappedFile {
-        let file_name = CString::new(name).unwrap();
+        let file_name = StackCString::new(&[name]).expect("Invalid file name");
         let mut already_existed = true;
 
         unsafe {
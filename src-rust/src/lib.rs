@@ -53,33 +53,63 @@ Happy Hacking 💜
 */
 
 #![cfg_attr(feature = "nightly", feature(optimize_attribute))]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 #![cfg_attr(not(feature = "std"), no_std)]
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 #[macro_use]
 extern crate alloc;
 
 pub mod structs {
 
     pub mod params {
+        pub mod allocation_placement;
+        pub use allocation_placement::AllocationPlacement;
+
+        pub mod branch_kind;
+        pub use branch_kind::BranchKind;
+
         pub mod buffer_allocator_settings;
         pub use buffer_allocator_settings::BufferAllocatorSettings;
 
+        pub mod buffer_manager_settings;
+        pub use buffer_manager_settings::BufferManagerSettings;
+
         pub mod buffer_search_settings;
         pub use buffer_search_settings::BufferSearchSettings;
     }
 
     pub mod errors {
         pub mod buffer_allocation_error;
-        pub use buffer_allocation_error::BufferAllocationError;
+        pub use buffer_allocation_error::{BufferAllocationError, BufferAllocationErrorKind};
 
         pub mod buffer_search_error;
         pub use buffer_search_error::BufferSearchError;
 
         pub mod item_allocation_error;
         pub use item_allocation_error::ItemAllocationError;
+
+        pub mod process_inspection_error;
+        pub use process_inspection_error::ProcessInspectionError;
     }
 
+    pub mod buffer_region;
+    pub use buffer_region::BufferRegion;
+
+    pub mod buffer_snapshot;
+    pub use buffer_snapshot::BufferSnapshot;
+
+    pub mod fixed_vec;
+    pub use fixed_vec::FixedVec;
+
+    #[cfg(all(target_os = "linux", feature = "alloc"))]
+    pub mod memory_map;
+    #[cfg(all(target_os = "linux", feature = "alloc"))]
+    pub use memory_map::{MemoryMap, MemoryMapRegion};
+
+    pub mod memory_permissions;
+    pub use memory_permissions::MemoryPermissions;
+
     pub mod safe_locator_item;
     pub use safe_locator_item::SafeLocatorItem;
 
@@ -99,9 +129,34 @@ pub mod structs {
     }
 }
 
+/// Backends through which the allocator queries and maps memory.
+///
+/// Allows running the allocation algorithm against a simulated address space.
+/// See [`buffers::Buffers::allocate_with_backend`].
+pub mod backends {
+    pub mod memory_backend;
+    pub use memory_backend::MemoryBackend;
+
+    #[cfg(feature = "alloc")]
+    pub mod simulated_backend;
+    #[cfg(feature = "alloc")]
+    pub use simulated_backend::{SimulatedBackend, SimulatedMapping};
+
+    #[cfg(target_os = "linux")]
+    pub mod linux_backend;
+    #[cfg(target_os = "linux")]
+    pub use linux_backend::LinuxBackend;
+
+    pub use crate::utilities::map_parser_utilities::MemoryMapEntry;
+}
+
 pub(crate) mod internal {
     pub mod buffer_allocator;
     pub mod locator_header_finder;
+    #[cfg(feature = "alloc")]
+    pub mod locator_index;
+    #[cfg(feature = "alloc")]
+    pub mod locator_snapshot;
 
     #[cfg(target_os = "linux")]
     pub mod buffer_allocator_linux;
@@ -132,12 +187,29 @@ pub(crate) mod utilities {
     pub mod address_range;
     pub mod cached;
     pub mod icache_clear;
+    pub mod lock_owner;
+
+    #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+    pub mod lock_diagnostics;
     pub mod map_parser_utilities;
     pub mod mathematics;
+    pub mod stack_c_string;
     pub mod wrappers;
 
+    #[cfg(target_os = "linux")]
+    pub mod linux_address_limits;
+    #[cfg(target_os = "linux")]
+    pub mod linux_huge_pages;
     #[cfg(target_os = "linux")]
     pub mod linux_map_parser;
+    #[cfg(target_os = "linux")]
+    pub mod linux_process_memory;
+    #[cfg(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        feature = "external_processes"
+    ))]
+    pub mod linux_remote_process;
 
     // Internal, disables W^X for internal buffers.
     pub(crate) mod disable_write_xor_execute;
@@ -153,4 +225,8 @@ pub mod c {
     pub mod buffers_c_locatoritem;
 }
 
+pub mod buffer_manager;
 pub mod buffers;
+
+#[cfg(feature = "nightly")]
+pub mod near_allocator;
@@ -0,0 +1,287 @@
+//! An [`Allocator`] which places allocations within a given address window.
+//!
+//! Lets you put ordinary collections (e.g. `Vec`s of hook metadata, `Box`ed thunks) near a target:
+//!
+//! ```
+//! #![feature(allocator_api)]
+//! use reloaded_memory_buffers::near_allocator::NearAllocator;
+//!
+//! let target = 0x7FFF_0000usize;
+//! let mut items = Vec::new_in(NearAllocator::new(target, i32::MAX as usize));
+//! items.push(1u64);
+//!
+//! let offset = (items.as_ptr() as usize).abs_diff(target);
+//! assert!(offset <= i32::MAX as usize);
+//! ```
+//!
+//! Requires the `nightly` feature.
+
+use crate::buffers::Buffers;
+use crate::structs::params::{BufferAllocatorSettings, BufferSearchSettings};
+use crate::structs::PrivateAllocation;
+use crate::utilities::mathematics::round_up;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::cell::Cell;
+use core::mem::size_of;
+use core::ptr::{read, write, NonNull};
+
+/// Minimum size of the chunks mapped at a time, unless overridden by the settings.
+const DEFAULT_CHUNK_SIZE: u32 = 4096;
+
+/// Link to the previously mapped chunk, stored at the start of each chunk.
+type ChunkLink = Option<PrivateAllocation>;
+
+/// Where chunks of memory come from.
+enum Arena {
+    /// Private allocations mapped within an address window as needed.
+    Chunks {
+        settings: BufferAllocatorSettings,
+
+        /// Most recently mapped chunk; each chunk links to the one before it.
+        last_chunk: Cell<ChunkLink>,
+    },
+
+    /// A single allocation, freed when the allocator is dropped.
+    Private { _allocation: PrivateAllocation },
+}
+
+/// Bump allocator which takes its memory from private allocations within an address window.
+///
+/// # Remarks
+///
+/// In line with the library's permanent memory model, memory is not reused once freed; except the
+/// most recent allocation, which is returned to the current chunk.
+///
+/// Chunks are mapped with [`Buffers::allocate_private_memory`] rather than taken from the shared
+/// buffers; they are writable on platforms enforcing W^X, and are freed when the allocator is dropped.
+pub struct NearAllocator {
+    arena: Arena,
+
+    /// Next free address in the current chunk.
+    next: Cell<usize>,
+
+    /// Address of the byte after the end of the current chunk.
+    end: Cell<usize>,
+
+    /// Address of the most recent allocation, which can be returned.
+    last: Cell<usize>,
+}
+
+impl NearAllocator {
+    /// Creates an allocator whose allocations are within `reach` bytes of `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Target address.
+    /// * `reach` - Max distance (number of bytes) of any allocated byte to `target`.
+    pub fn new(target: usize, reach: usize) -> Self {
+        Self::with_settings(BufferSearchSettings::from_proximity(
+            reach,
+            target,
+            DEFAULT_CHUNK_SIZE as usize,
+        ))
+    }
+
+    /// Creates an allocator which maps memory within the address window of the given settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Address window to allocate in, and how to place chunks within it; `size` is the
+    ///   minimum size of chunks mapped at a time. Larger allocations get a chunk of their own.
+    pub fn with_settings(settings: BufferSearchSettings) -> Self {
+        let allocator_settings = BufferAllocatorSettings {
+            min_address: settings.min_address,
+            max_address: settings.max_address,
+            size: settings.size,
+            placement: settings.placement,
+            target_address: settings.target_address,
+            huge_pages: settings.huge_pages,
+            ..BufferAllocatorSettings::new()
+        };
+
+        Self::from_arena(
+            Arena::Chunks {
+                settings: allocator_settings,
+                last_chunk: Cell::new(None),
+            },
+            0,
+            0,
+        )
+    }
+
+    /// Creates an allocator which takes memory from a private allocation, e.g. one made with
+    /// [`Buffers::allocate_private_memory`].
+    ///
+    /// # Remarks
+    ///
+    /// Allocations fail once the private allocation is full. It is freed when the allocator is dropped.
+    pub fn from_private_allocation(allocation: PrivateAllocation) -> Self {
+        let start = allocation.base_address().as_ptr() as usize;
+        let end = start + allocation.size();
+        Self::from_arena(
+            Arena::Private {
+                _allocation: allocation,
+            },
+            start,
+            end,
+        )
+    }
+
+    fn from_arena(arena: Arena, next: usize, end: usize) -> Self {
+        Self {
+            arena,
+            next: Cell::new(next),
+            end: Cell::new(end),
+            last: Cell::new(0),
+        }
+    }
+
+    /// Bump allocates from the current chunk.
+    fn try_bump(&self, layout: Layout) -> Option<usize> {
+        if self.next.get() == 0 {
+            return None;
+        }
+
+        let address = round_up(self.next.get(), layout.align());
+        let end = address.checked_add(layout.size())?;
+        if end > self.end.get() {
+            return None;
+        }
+
+        self.next.set(end);
+        self.last.set(address);
+        Some(address)
+    }
+
+    /// Maps a new chunk, with space for the given layout.
+    fn refill(
+        &self,
+        settings: &BufferAllocatorSettings,
+        last_chunk: &Cell<ChunkLink>,
+        layout: Layout,
+    ) -> Result<(), AllocError> {
+        let required = layout
+            .size()
+            .checked_add(layout.align() - 1)
+            .and_then(|x| x.checked_add(size_of::<ChunkLink>()))
+            .and_then(|x| u32::try_from(x).ok())
+            .ok_or(AllocError)?;
+
+        let mut settings = *settings;
+        settings.size = settings.size.max(required);
+
+        let allocation = Buffers::allocate_private_memory(&mut settings).map_err(|_| AllocError)?;
+        let start = allocation.base_address().as_ptr() as usize;
+        let end = start + allocation.size();
+
+        // Link the previous chunk from this one, so it can be freed on drop.
+        unsafe { write(start as *mut ChunkLink, last_chunk.take()) };
+        last_chunk.set(Some(allocation));
+
+        self.next.set(start + size_of::<ChunkLink>());
+        self.end.set(end);
+        Ok(())
+    }
+}
+
+impl Drop for NearAllocator {
+    fn drop(&mut self) {
+        if let Arena::Chunks { last_chunk, .. } = &self.arena {
+            let mut chunk = last_chunk.take();
+            while let Some(allocation) = chunk {
+                chunk = unsafe { read(allocation.base_address().as_ptr() as *const ChunkLink) };
+            }
+        }
+    }
+}
+
+unsafe impl Allocator for NearAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let address = match self.try_bump(layout) {
+            Some(address) => address,
+            None => match &self.arena {
+                Arena::Chunks {
+                    settings,
+                    last_chunk,
+                } => {
+                    self.refill(settings, last_chunk, layout)?;
+                    self.try_bump(layout).ok_or(AllocError)?
+                }
+                Arena::Private { .. } => return Err(AllocError),
+            },
+        };
+
+        let ptr = NonNull::new(address as *mut u8).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let address = ptr.as_ptr() as usize;
+        if address == self.last.get() && address + layout.size() == self.next.get() {
+            self.next.set(address);
+            self.last.set(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::params::BufferAllocatorSettings;
+    use crate::utilities::cached::get_sys_info;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    #[test]
+    fn allocations_should_be_within_window() {
+        let target = get_sys_info().max_address / 2;
+        let reach = 0x1000_0000;
+        let allocator = NearAllocator::new(target, reach);
+
+        let mut items = Vec::new_in(&allocator);
+        items.extend(0..10_000u32);
+        let boxed = Box::new_in([0u8; 8192], &allocator);
+
+        for address in [items.as_ptr() as usize, boxed.as_ptr() as usize] {
+            assert!(address.abs_diff(target) <= reach);
+        }
+
+        assert!((items.as_ptr() as usize).is_multiple_of(4));
+        assert_eq!(items[9_999], 9_999);
+    }
+
+    #[test]
+    fn allocations_should_not_use_shared_buffers() {
+        let allocator = NearAllocator::new(get_sys_info().max_address / 2, 0x1000_0000);
+        let value = Box::new_in(0u64, &allocator);
+        let address = &*value as *const u64 as usize;
+
+        assert!(Buffers::get_buffer_snapshots()
+            .iter()
+            .all(|x| address < x.base_address || address >= x.base_address + x.size as usize));
+    }
+
+    #[test]
+    fn deallocate_should_return_most_recent_allocation() {
+        let allocator = NearAllocator::new(get_sys_info().max_address / 2, 0x1000_0000);
+        let layout = Layout::new::<u64>();
+
+        let first = allocator.allocate(layout).unwrap();
+        unsafe { allocator.deallocate(first.cast(), layout) };
+        let second = allocator.allocate(layout).unwrap();
+        assert_eq!(first.cast::<u8>(), second.cast::<u8>());
+    }
+
+    #[test]
+    fn private_allocation_should_fail_when_full() {
+        let mut settings = BufferAllocatorSettings::new();
+        let allocation = Buffers::allocate_private_memory(&mut settings).unwrap();
+        let size = allocation.size();
+        let allocator = NearAllocator::from_private_allocation(allocation);
+
+        assert!(allocator
+            .allocate(Layout::from_size_align(size, 1).unwrap())
+            .is_ok());
+        assert!(allocator.allocate(Layout::new::<u8>()).is_err());
+    }
+}
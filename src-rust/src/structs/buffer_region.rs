@@ -0,0 +1,120 @@
+use crate::utilities::disable_write_xor_execute::{
+    disable_write_xor_execute, restore_write_xor_execute,
+};
+use crate::utilities::icache_clear::clear_instruction_cache;
+use core::mem::{size_of, transmute_copy};
+use core::ptr::copy_nonoverlapping;
+use core::slice::from_raw_parts;
+
+/// A range of memory written to a buffer, e.g. a piece of code.
+///
+/// # Remarks
+///
+/// Keep this around to validate or overwrite the data later, e.g. to patch the target of a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BufferRegion {
+    /// Address of the first byte of the region.
+    pub address: usize,
+
+    /// Length of the region in bytes.
+    pub length: usize,
+}
+
+impl BufferRegion {
+    /// Creates a region from its address and length.
+    pub fn new(address: usize, length: usize) -> Self {
+        Self { address, length }
+    }
+
+    /// Returns the address of the byte after the last byte of the region.
+    pub fn end_address(&self) -> usize {
+        self.address + self.length
+    }
+
+    /// Returns true if the given address is inside the region.
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.address && address < self.end_address()
+    }
+
+    /// Returns the contents of the region.
+    ///
+    /// # Safety
+    ///
+    /// The region must be mapped in the current process.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        from_raw_parts(self.address as *const u8, self.length)
+    }
+
+    /// Returns the start of the region as a function pointer, e.g. `extern "C" fn() -> u64`.
+    ///
+    /// # Panics
+    ///
+    /// If `F` is not pointer sized.
+    ///
+    /// # Safety
+    ///
+    /// The region must contain a function with the signature and calling convention of `F`.
+    pub unsafe fn as_function<F: Copy>(&self) -> F {
+        assert_eq!(
+            size_of::<F>(),
+            size_of::<usize>(),
+            "function pointer type must be pointer sized"
+        );
+
+        transmute_copy(&self.address)
+    }
+
+    /// Overwrites the start of the region, e.g. to patch previously written code.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to write.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the data was written, `false` if it does not fit in the region.
+    ///
+    /// # Remarks
+    ///
+    /// The instruction cache is cleared for the written bytes, so this is suitable for code.
+    ///
+    /// # Safety
+    ///
+    /// The region must be mapped in the current process, and not be in use (e.g. executed)
+    /// at the time of the write.
+    pub unsafe fn overwrite(&self, data: &[u8]) -> bool {
+        if data.len() > self.length {
+            return false;
+        }
+
+        disable_write_xor_execute(self.address as *const u8, data.len());
+        copy_nonoverlapping(data.as_ptr(), self.address as *mut u8, data.len());
+        restore_write_xor_execute(self.address as *const u8, data.len());
+        clear_instruction_cache(
+            self.address as *mut u8,
+            (self.address + data.len()) as *mut u8,
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwrite_should_respect_length() {
+        let mut data = [0u8; 4];
+        let region = BufferRegion::new(data.as_mut_ptr() as usize, data.len());
+
+        unsafe {
+            assert!(region.overwrite(&[1, 2]));
+            assert!(!region.overwrite(&[1, 2, 3, 4, 5]));
+            assert_eq!(region.as_bytes(), &[1, 2, 0, 0]);
+        }
+
+        assert!(region.contains(region.address + 3));
+        assert!(!region.contains(region.end_address()));
+    }
+}
@@ -0,0 +1,44 @@
+use crate::structs::internal::LocatorItem;
+
+/// A copy of the state of a single buffer, taken without locking it.
+///
+/// # Remarks
+///
+/// The buffer may be in use by another thread or process while the snapshot is taken,
+/// so the values are only a point in time view; they are intended for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferSnapshot {
+    /// Address of the buffer in the memory of the process that owns it.
+    pub base_address: usize,
+
+    /// Size of the buffer.
+    pub size: u32,
+
+    /// Number of bytes of the buffer already used.
+    pub position: u32,
+
+    /// True if the buffer was locked at the time of the snapshot.
+    pub is_taken: bool,
+
+    /// ID of the thread that held the lock, if known.
+    pub owner_thread_id: Option<u32>,
+}
+
+impl BufferSnapshot {
+    /// Returns the amount of bytes left in the buffer.
+    pub fn bytes_left(&self) -> u32 {
+        self.size.saturating_sub(self.position)
+    }
+}
+
+impl From<&LocatorItem> for BufferSnapshot {
+    fn from(item: &LocatorItem) -> Self {
+        Self {
+            base_address: item.base_address.value,
+            size: item.size,
+            position: item.position,
+            is_taken: item.is_taken(),
+            owner_thread_id: item.owner_thread_id(),
+        }
+    }
+}
@@ -1,16 +1,72 @@
 use crate::structs::params::BufferAllocatorSettings;
+use core::fmt::{Display, Formatter};
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
 
-#[derive(Debug, Clone)]
+/// Reason why a buffer could not be allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAllocationErrorKind {
+    /// The settings can never be satisfied; e.g. `min_address` is not below `max_address`,
+    /// or `size` is larger than the range between them.
+    InvalidSettings,
+
+    /// The target process could not be opened, or its memory could not be queried.
+    ProcessUnavailable {
+        /// Error code reported by the OS (`errno` or `GetLastError`), 0 if unknown.
+        os_error: i32,
+    },
+
+    /// None of the free regions in the requested range could fit the buffer.
+    NoSpaceInRange,
+
+    /// The OS refused to map memory at a suitable free address; e.g. `EACCES`/`EPERM` when a
+    /// security policy (such as SELinux `execmem`) denies executable memory.
+    MapFailed {
+        /// Error code reported by the OS (`errno`, `GetLastError` or `kern_return_t`).
+        os_error: i32,
+    },
+
+    /// More free regions lie in the requested range than can be stored without the `alloc` feature;
+    /// narrow the range between `min_address` and `max_address`.
+    TooManyFreeRegions,
+}
+
+impl Display for BufferAllocationErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BufferAllocationErrorKind::InvalidSettings => f.write_str("Invalid settings"),
+            BufferAllocationErrorKind::ProcessUnavailable { os_error } => {
+                write!(f, "Process unavailable (os error {})", os_error)
+            }
+            BufferAllocationErrorKind::NoSpaceInRange => f.write_str("No space in range"),
+            BufferAllocationErrorKind::MapFailed { os_error } => {
+                write!(f, "Failed to map memory (os error {})", os_error)
+            }
+            BufferAllocationErrorKind::TooManyFreeRegions => f.write_str("Too many free regions"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BufferAllocationError {
     pub settings: BufferAllocatorSettings,
     pub text: &'static str,
+
+    /// Reason for the failure.
+    pub kind: BufferAllocationErrorKind,
+
+    /// Number of free regions examined during the last allocation attempt.
+    pub regions_examined: usize,
+
+    /// Free region closest to the requested range, as `(start, end)`.
+    /// This is `None` if no free regions were examined.
+    pub nearest_free_region: Option<(usize, usize)>,
 }
 
 #[allow(clippy::inherent_to_string_shadow_display)]
 impl BufferAllocationError {
+    #[cfg(feature = "alloc")]
     pub fn to_string(&self) -> String {
         // We save some space here for C binding use.
         #[cfg(feature = "no_format")]
@@ -22,17 +78,14 @@ impl BufferAllocationError {
 
         #[cfg(not(feature = "no_format"))]
         {
-            format!(
-                "Buffer Allocation Error: {}. Settings: {:?}",
-                self.text, self.settings
-            )
+            format!("{}", self)
         }
     }
 }
 
-impl core::fmt::Display for BufferAllocationError {
+impl Display for BufferAllocationError {
     #[cfg_attr(feature = "size_opt", optimize(size))]
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         #[cfg(feature = "no_format")]
         {
             f.write_str("Buffer Allocation Error: ")?;
@@ -43,15 +96,38 @@ impl core::fmt::Display for BufferAllocationError {
         {
             write!(
                 f,
-                "Buffer Allocation Error: {}. Settings: {:?}",
-                self.text, self.settings
-            )
+                "Buffer Allocation Error: {}. Kind: {}. Free Regions Examined: {}",
+                self.text, self.kind, self.regions_examined
+            )?;
+
+            if let Some((start, end)) = self.nearest_free_region {
+                write!(f, ". Nearest Free Region: {:#X}-{:#X}", start, end)?;
+            }
+
+            write!(f, ". Settings: {:?}", self.settings)
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for BufferAllocationError {}
+
 impl BufferAllocationError {
     pub fn new(settings: BufferAllocatorSettings, text: &'static str) -> Self {
-        Self { settings, text }
+        Self::with_kind(settings, text, BufferAllocationErrorKind::NoSpaceInRange)
+    }
+
+    pub fn with_kind(
+        settings: BufferAllocatorSettings,
+        text: &'static str,
+        kind: BufferAllocationErrorKind,
+    ) -> Self {
+        Self {
+            settings,
+            text,
+            kind,
+            regions_examined: 0,
+            nearest_free_region: None,
+        }
     }
 }
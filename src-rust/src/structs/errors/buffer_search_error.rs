@@ -1,17 +1,25 @@
+#[cfg(feature = "alloc")]
+use crate::structs::errors::BufferAllocationError;
 use crate::structs::params::BufferSearchSettings;
 use core::fmt::{Display, Formatter};
 
-#[cfg(not(feature = "std"))]
-use alloc::string::String;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::{boxed::Box, string::String};
 
 #[derive(Debug, Clone)]
 pub struct BufferSearchError {
     pub settings: BufferSearchSettings,
     pub text: &'static str,
+
+    /// Error from allocating a new buffer, if that is why the search failed.
+    /// Boxed, as it's large and only needed on the failure path.
+    #[cfg(feature = "alloc")]
+    pub allocation_error: Option<Box<BufferAllocationError>>,
 }
 
 #[allow(clippy::inherent_to_string_shadow_display)]
 impl BufferSearchError {
+    #[cfg(feature = "alloc")]
     pub fn to_string(&self) -> String {
         #[cfg(feature = "no_format")]
         {
@@ -22,10 +30,7 @@ impl BufferSearchError {
 
         #[cfg(not(feature = "no_format"))]
         {
-            format!(
-                "Buffer Search Error: {}. Settings: {:?}",
-                self.text, self.settings
-            )
+            format!("{}", self)
         }
     }
 }
@@ -46,13 +51,35 @@ impl Display for BufferSearchError {
                 f,
                 "Buffer Search Error: {}. Settings: {:?}",
                 self.text, self.settings
-            )
+            )?;
+
+            #[cfg(feature = "alloc")]
+            if let Some(error) = &self.allocation_error {
+                write!(f, ". Caused by: {}", error)?;
+            }
+
+            Ok(())
         }
     }
 }
 
 impl BufferSearchError {
     pub fn new(settings: BufferSearchSettings, text: &'static str) -> Self {
-        Self { settings, text }
+        Self {
+            settings,
+            text,
+            #[cfg(feature = "alloc")]
+            allocation_error: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BufferSearchError {
+    #[cfg(feature = "alloc")]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.allocation_error
+            .as_deref()
+            .map(|x| x as &(dyn std::error::Error + 'static))
     }
 }
@@ -1,14 +1,33 @@
-#[derive(PartialEq)]
+use crate::structs::errors::BufferAllocationError;
+use core::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ItemAllocationError {
     NoSpaceInHeader,
-    CannotAllocateMemory,
+    CannotAllocateMemory(BufferAllocationError),
 }
 
 impl ItemAllocationError {
     pub fn as_string(&self) -> &'static str {
         match self {
             ItemAllocationError::NoSpaceInHeader => "No more space in locator header",
-            ItemAllocationError::CannotAllocateMemory => "Could not allocate memory",
+            ItemAllocationError::CannotAllocateMemory(_) => "Could not allocate memory",
+        }
+    }
+}
+
+impl Display for ItemAllocationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ItemAllocationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ItemAllocationError::NoSpaceInHeader => None,
+            ItemAllocationError::CannotAllocateMemory(error) => Some(error),
         }
     }
 }
@@ -0,0 +1,65 @@
+use core::fmt::{Display, Formatter};
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::string::String;
+
+/// Error returned when the buffers of another process could not be inspected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInspectionError {
+    /// ID of the process that was being inspected.
+    pub process_id: u32,
+    pub text: &'static str,
+
+    /// Error code returned by the operating system, 0 if not applicable.
+    pub os_error: i32,
+}
+
+#[allow(clippy::inherent_to_string_shadow_display)]
+impl ProcessInspectionError {
+    pub fn new(process_id: u32, text: &'static str, os_error: i32) -> Self {
+        Self {
+            process_id,
+            text,
+            os_error,
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn to_string(&self) -> String {
+        #[cfg(feature = "no_format")]
+        {
+            use nanokit::string_concat::concat_2;
+            const BASE_MSG: &str = "Process Inspection Error: ";
+            concat_2(BASE_MSG, self.text)
+        }
+
+        #[cfg(not(feature = "no_format"))]
+        {
+            format!("{}", self)
+        }
+    }
+}
+
+impl Display for ProcessInspectionError {
+    #[cfg_attr(feature = "size_opt", optimize(size))]
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        #[cfg(feature = "no_format")]
+        {
+            const BASE_MSG: &str = "Process Inspection Error: ";
+            f.write_str(BASE_MSG)?;
+            f.write_str(self.text)
+        }
+
+        #[cfg(not(feature = "no_format"))]
+        {
+            write!(
+                f,
+                "Process Inspection Error: {}. Process: {}, OS error: {}",
+                self.text, self.process_id, self.os_error
+            )
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProcessInspectionError {}
@@ -0,0 +1,137 @@
+use core::ops::{Deref, DerefMut};
+
+/// A list with a fixed capacity, stored inline (e.g. on the stack) without allocating.
+///
+/// # Remarks
+///
+/// Used in place of `Vec` without the `alloc` feature. Items pushed once the list is full are dropped.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedVec<T: Copy + Default, const N: usize> {
+    items: [T; N],
+    length: usize,
+}
+
+impl<T: Copy + Default, const N: usize> FixedVec<T, N> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self {
+            items: [T::default(); N],
+            length: 0,
+        }
+    }
+
+    /// Appends an item to the list.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the list is full, in which case the item is dropped.
+    pub fn push(&mut self, item: T) -> bool {
+        if self.length == N {
+            return false;
+        }
+
+        self.items[self.length] = item;
+        self.length += 1;
+        true
+    }
+
+    /// Keeps only the items for which `keep` returns true, allowing them to be modified.
+    pub fn retain_mut(&mut self, mut keep: impl FnMut(&mut T) -> bool) {
+        let mut kept = 0;
+        for index in 0..self.length {
+            let mut item = self.items[index];
+            if keep(&mut item) {
+                self.items[kept] = item;
+                kept += 1;
+            }
+        }
+
+        self.length = kept;
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items[..self.length]
+    }
+}
+
+impl<T: Copy + Default, const N: usize> DerefMut for FixedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.length]
+    }
+}
+
+impl<T: Copy + Default, const N: usize> FromIterator<T> for FixedVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut result = Self::new();
+        for item in iter {
+            result.push(item);
+        }
+
+        result
+    }
+}
+
+impl<T: Copy + Default, const N: usize> IntoIterator for FixedVec<T, N> {
+    type Item = T;
+    type IntoIter = core::iter::Take<core::array::IntoIter<T, N>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter().take(self.length)
+    }
+}
+
+impl<'a, T: Copy + Default, const N: usize> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Copy + Default + PartialEq, const N: usize> PartialEq for FixedVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Copy + Default + Eq, const N: usize> Eq for FixedVec<T, N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_should_drop_items_when_full() {
+        let mut list = FixedVec::<u32, 2>::new();
+        assert!(list.push(1));
+        assert!(list.push(2));
+        assert!(!list.push(3));
+        assert_eq!(&*list, &[1, 2]);
+    }
+
+    #[test]
+    fn retain_mut_should_keep_modified_items() {
+        let mut list = FixedVec::<u32, 4>::new();
+        for x in 1..=4 {
+            list.push(x);
+        }
+
+        list.retain_mut(|x| {
+            *x *= 10;
+            *x != 20
+        });
+
+        assert_eq!(&*list, &[10, 30, 40]);
+    }
+}
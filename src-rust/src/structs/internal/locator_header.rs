@@ -1,18 +1,23 @@
-extern crate alloc;
 use crate::internal::buffer_allocator::allocate;
-use crate::structs::errors::ItemAllocationError;
+use crate::structs::errors::{BufferAllocationError, ItemAllocationError};
 use crate::structs::internal::LocatorItem;
-use crate::structs::params::BufferAllocatorSettings;
+use crate::structs::params::{BufferAllocatorSettings, BufferSearchSettings};
 use crate::structs::SafeLocatorItem;
 use crate::utilities::cached::get_sys_info;
+use crate::utilities::lock_owner::{current_owner_value, owner_thread_id, try_recover};
+use crate::utilities::mathematics::round_up;
 use crate::utilities::wrappers::Unaligned;
-use core::alloc::Layout;
-use core::cell::Cell;
-use core::cmp::min;
+use core::cmp::{max, min};
 use core::mem::size_of;
 use core::ptr::null_mut;
 use core::sync::atomic::{AtomicI32, Ordering};
 
+#[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+use {
+    crate::utilities::lock_diagnostics::{on_acquired, on_released, LockWait},
+    core::panic::Location,
+};
+
 /// Static length of this locator.
 pub(crate) const LENGTH: usize = 4096;
 
@@ -28,6 +33,23 @@ pub(crate) const LENGTH: usize = 4096;
 /// and pre-register them as buffers.
 pub(crate) const LENGTH_OF_PREALLOCATED_CHUNKS: u32 = 16384;
 
+/// Returns the offset of the first preallocated buffer from the start of a locator.
+///
+/// # Remarks
+///
+/// Buffers start on the first page boundary after the header, so changing their protection
+/// never affects the header; on systems with pages larger than [`LENGTH`] (e.g. 16K/64K on aarch64)
+/// the rest of the header's page is left unused.
+pub(crate) fn get_buffers_offset() -> usize {
+    round_up(LENGTH, get_sys_info().page_size as usize)
+}
+
+/// Returns the length of buffers preallocated in a locator; [`LENGTH_OF_PREALLOCATED_CHUNKS`]
+/// or the page size, whichever is larger.
+pub(crate) fn get_preallocated_chunk_length() -> u32 {
+    max(LENGTH_OF_PREALLOCATED_CHUNKS, get_sys_info().page_size)
+}
+
 /// Returns the maximum possible amount of items in this locator.
 pub(crate) const MAX_ITEM_COUNT: u32 =
     ((LENGTH - size_of::<LocatorHeader>()) / size_of::<LocatorItem>()) as u32;
@@ -65,7 +87,6 @@ impl LocatorHeader {
     /// * `length` - Number of bytes available.
     pub(crate) fn initialize(&mut self, length: usize) {
         self.set_default_values();
-        let remaining_bytes = (length - LENGTH) as u32;
 
         // We allocate to allocation_granularity, however, under some platforms (*cough* M1 macOS)
         // W^X policy is enforced, in which case, we cannot allocate executable memory here,
@@ -74,10 +95,15 @@ impl LocatorHeader {
         // We will use the remaining space for more headers on these affected platforms, and
         // on non-W^X platforms, we will use it for buffers.
         #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-        Self::initialize_remaining_space_as_headers(self as *mut LocatorHeader, remaining_bytes);
+        Self::initialize_remaining_space_as_headers(
+            self as *mut LocatorHeader,
+            length.saturating_sub(LENGTH) as u32,
+        );
 
         #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
-        self.initialize_remaining_space_as_buffers(remaining_bytes);
+        self.initialize_remaining_space_as_buffers(
+            length.saturating_sub(get_buffers_offset()) as u32
+        );
     }
 
     fn set_default_values(&mut self) {
@@ -90,14 +116,16 @@ impl LocatorHeader {
 
     fn initialize_remaining_space_as_buffers(&mut self, mut remaining_bytes: u32) {
         let mut num_items = 0u8;
+        let chunk_length = get_preallocated_chunk_length();
         unsafe {
-            let buffer_address = (self.this_address.value as *mut u8).add(LENGTH);
+            let mut buffer_address = (self.this_address.value as *mut u8).add(get_buffers_offset());
             let mut current_item = self.get_first_item();
 
             while remaining_bytes > 0 {
-                let this_length = min(LENGTH_OF_PREALLOCATED_CHUNKS, remaining_bytes);
+                let this_length = min(chunk_length, remaining_bytes);
                 *current_item = LocatorItem::new(buffer_address as usize, this_length);
                 current_item = current_item.offset(1);
+                buffer_address = buffer_address.add(this_length as usize);
                 remaining_bytes -= this_length;
                 num_items += 1;
             }
@@ -142,20 +170,67 @@ impl LocatorHeader {
         self.num_items as usize >= MAX_ITEM_COUNT as usize
     }
 
+    /// Returns the ID of the thread which holds the lock on this header.
+    ///
+    /// Returns `None` if the header is not locked, or if the owner is unknown
+    /// (e.g. it was locked by an older version of the library).
+    pub fn owner_thread_id(&self) -> Option<u32> {
+        owner_thread_id(self.is_locked.load(Ordering::Acquire))
+    }
+
     /// Tries to acquire the lock.
     ///
     /// Returns: True if the lock was successfully acquired, false otherwise.
+    ///
+    /// # Remarks
+    ///
+    /// If the lock is held by a thread which no longer exists, the lock is taken over.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn try_lock(&mut self) -> bool {
         // Since Rust doesn't have a direct equivalent of C#'s `Interlocked.CompareExchange`,
         // we need to use the atomic operations from the `std::sync::atomic` module.
-        self.is_locked
-            .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
-            .is_ok()
+        let owner = current_owner_value();
+        match self
+            .is_locked
+            .compare_exchange(0, owner, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+                on_acquired(self as *const Self as usize, Location::caller());
+                true
+            }
+            Err(current) => {
+                let recovered = try_recover(&self.is_locked, current, owner);
+
+                #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+                if recovered {
+                    on_acquired(self as *const Self as usize, Location::caller());
+                }
+
+                recovered
+            }
+        }
     }
 
     /// Acquires the lock, blocking until it can do so.
+    ///
+    /// # Panics
+    ///
+    /// With the `lock_diagnostics` feature in debug builds, if the lock is already held by the
+    /// current thread. Waits exceeding a threshold are also reported to stderr.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn lock(&mut self) {
+        #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+        let mut wait = LockWait::new(
+            "LocatorHeader",
+            self as *const Self as usize,
+            self.is_locked.load(Ordering::Acquire),
+        );
+
         while !self.try_lock() {
+            #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+            wait.on_spin(self.is_locked.load(Ordering::Acquire));
+
             #[cfg(all(feature = "std", not(unix), not(windows)))]
             {
                 std::thread::yield_now();
@@ -180,6 +255,9 @@ impl LocatorHeader {
     /// If the buffer is already unlocked, this error is thrown.
     /// It is only thrown in debug mode.
     pub fn unlock(&mut self) {
+        #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+        on_released(self as *const Self as usize);
+
         // Set _is_locked to 0 and return the original value.
         let original = self.is_locked.swap(0, Ordering::AcqRel);
 
@@ -219,12 +297,13 @@ impl LocatorHeader {
     ///
     /// Returns a locked locator item. Make sure to properly dispose of it using the appropriate method,
     /// as disposing will release the lock.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub unsafe fn get_first_available_item_locked(
         &self,
         size: u32,
         min_address: usize,
         max_address: usize,
-    ) -> Option<SafeLocatorItem> {
+    ) -> Option<SafeLocatorItem<'static>> {
         let mut current_item = self.get_first_item();
         let final_item = current_item.add(self.num_items as usize);
         while current_item < final_item {
@@ -232,9 +311,7 @@ impl LocatorHeader {
             if item_ref.can_use(size, min_address, max_address) && item_ref.try_lock() {
                 return Some({
                     let item: *mut LocatorItem = item_ref;
-                    SafeLocatorItem {
-                        item: Cell::new(item),
-                    }
+                    SafeLocatorItem::new(item)
                 });
             }
 
@@ -248,9 +325,8 @@ impl LocatorHeader {
     ///
     /// # Arguments
     ///
-    /// * `size` - Required size of buffer.
-    /// * `min_address` - Minimum address for the allocation.
-    /// * `max_address` - Maximum address for the allocation.
+    /// * `settings` - Requirements of the buffer; its size, address range, and how it should be placed
+    ///   and backed.
     ///
     /// # Returns
     ///
@@ -268,12 +344,41 @@ impl LocatorHeader {
     ///
     /// This function is unsafe as it requires the caller to ensure that calls are properly synchronized.
     /// Concurrent access without synchronization can lead to undefined behavior.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn try_allocate_item(
         &mut self,
-        size: u32,
-        min_address: usize,
-        max_address: usize,
-    ) -> Result<SafeLocatorItem, ItemAllocationError> {
+        settings: &BufferSearchSettings,
+    ) -> Result<SafeLocatorItem<'static>, ItemAllocationError> {
+        self.try_allocate_item_with(|| {
+            let mut allocator_settings = BufferAllocatorSettings::new();
+            allocator_settings.min_address = settings.min_address;
+            allocator_settings.max_address = settings.max_address;
+            allocator_settings.size = settings.size;
+            allocator_settings.placement = settings.placement;
+            allocator_settings.target_address = settings.target_address;
+            allocator_settings.huge_pages = settings.huge_pages;
+            allocate(&mut allocator_settings)
+        })
+    }
+
+    /// Tries to allocate an additional item in the header with the given function, if there is a
+    /// free slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `allocate` - Allocates the memory of the item; called with the header locked.
+    ///
+    /// # Remarks
+    ///
+    /// See [`LocatorHeader::try_allocate_item`].
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub fn try_allocate_item_with<F>(
+        &mut self,
+        allocate: F,
+    ) -> Result<SafeLocatorItem<'static>, ItemAllocationError>
+    where
+        F: FnOnce() -> Result<LocatorItem, BufferAllocationError>,
+    {
         if self.is_full() {
             return Err(ItemAllocationError::NoSpaceInHeader);
         }
@@ -288,31 +393,23 @@ impl LocatorHeader {
             return Err(ItemAllocationError::NoSpaceInHeader);
         }
 
-        let mut settings = BufferAllocatorSettings::new();
-        settings.min_address = min_address;
-        settings.max_address = max_address;
-        settings.size = size;
-        let result = allocate(&mut settings);
-
-        match result {
+        match allocate() {
             Ok(mut allocated_memory) => {
                 allocated_memory.lock();
 
                 unsafe {
                     let target = self.get_item(self.num_items as usize);
                     *target = allocated_memory;
-                    let item = SafeLocatorItem {
-                        item: Cell::new(target),
-                    };
+                    let item = SafeLocatorItem::new(target);
 
                     self.num_items += 1;
                     self.unlock();
                     Ok(item)
                 }
             }
-            Err(_) => {
+            Err(error) => {
                 self.unlock();
-                Err(ItemAllocationError::CannotAllocateMemory)
+                Err(ItemAllocationError::CannotAllocateMemory(error))
             }
         }
     }
@@ -323,7 +420,71 @@ impl LocatorHeader {
     ///
     /// Result with the address of next header, or error string.
     ///
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn get_next_locator(&mut self) -> Result<*mut LocatorHeader, &'static str> {
+        self.get_next_locator_near(0, get_sys_info().max_address)
+    }
+
+    /// Gets the next header in the chain, allocating it if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_address` - Preferred minimum address of the new locator.
+    /// * `max_address` - Preferred maximum address of the new locator.
+    ///
+    /// # Returns
+    ///
+    /// Result with the address of next header, or error string.
+    ///
+    /// # Remarks
+    ///
+    /// The new locator is allocated with the buffer allocator, and the remaining space after the
+    /// header is registered as buffers. Placing it in the address range of the request which caused
+    /// the chain to grow means those buffers are likely to be useful for the next similar request.
+    ///
+    /// If no memory can be allocated within the given range, the locator is allocated anywhere.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub fn get_next_locator_near(
+        &mut self,
+        min_address: usize,
+        max_address: usize,
+    ) -> Result<*mut LocatorHeader, &'static str> {
+        self.get_next_locator_with(|| {
+            let sys_info = get_sys_info();
+            let mut settings = BufferAllocatorSettings::new();
+            settings.min_address = min_address;
+            settings.max_address = max_address;
+            settings.size = sys_info.allocation_granularity as u32;
+
+            let mut result = allocate(&mut settings);
+            if result.is_err() && (min_address != 0 || max_address != sys_info.max_address) {
+                settings.min_address = 0;
+                settings.max_address = sys_info.max_address;
+                result = allocate(&mut settings);
+            }
+
+            result
+        })
+    }
+
+    /// Gets the next header in the chain, allocating it with the given function if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `allocate` - Allocates the memory of the new locator; called with this header locked.
+    ///   The whole allocation is used by the locator, see [`LocatorHeader::initialize`].
+    ///
+    /// # Returns
+    ///
+    /// Result with the address of next header, or error string.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
+    pub fn get_next_locator_with<F>(
+        &mut self,
+        allocate: F,
+    ) -> Result<*mut LocatorHeader, &'static str>
+    where
+        F: FnOnce() -> Result<LocatorItem, BufferAllocationError>,
+    {
         // No-op if already exists.
         if self.has_next_locator() {
             return Ok(self.next_locator_ptr.value);
@@ -337,25 +498,19 @@ impl LocatorHeader {
             return Ok(self.next_locator_ptr.value);
         }
 
-        // Allocate the next locator.
-        let sys_info = get_sys_info();
-        let alloc_size = sys_info.allocation_granularity;
-        unsafe {
-            let addr = alloc::alloc::alloc(
-                Layout::from_size_align(alloc_size as usize, sys_info.page_size as usize).unwrap(),
-            );
-            if addr.is_null() {
+        match allocate() {
+            Ok(allocated_memory) => unsafe {
+                self.next_locator_ptr.value =
+                    allocated_memory.base_address.value as *mut LocatorHeader;
+                (*self.next_locator_ptr.value).initialize(allocated_memory.size as usize);
                 self.unlock();
-                return Err(
-                    "Failed to allocate memory for LocatorHeader. Is this process out of memory?",
-                );
-            }
-
-            self.next_locator_ptr.value = addr as *mut LocatorHeader;
-            (*self.next_locator_ptr.value).initialize(alloc_size as usize);
-            self.unlock();
 
-            Ok(self.next_locator_ptr.value)
+                Ok(self.next_locator_ptr.value)
+            },
+            Err(_) => {
+                self.unlock();
+                Err("Failed to allocate memory for LocatorHeader. Is this process out of memory?")
+            }
         }
     }
 }
@@ -363,11 +518,15 @@ impl LocatorHeader {
 #[cfg(test)]
 mod tests {
     extern crate std;
-    use crate::structs::internal::locator_header::{Unaligned, LENGTH, MAX_ITEM_COUNT};
+    use crate::structs::internal::locator_header::{
+        get_buffers_offset, get_preallocated_chunk_length, Unaligned, LENGTH, MAX_ITEM_COUNT,
+    };
     use crate::structs::internal::LocatorHeader;
+    use crate::structs::params::BufferSearchSettings;
     use crate::utilities::cached::get_sys_info;
+    use crate::utilities::lock_owner::current_owner_value;
     use memoffset::offset_of;
-    use std::alloc::{alloc, Layout};
+    use std::alloc::{alloc, dealloc, Layout};
     use std::mem::{align_of, size_of};
     use std::sync::atomic::Ordering;
 
@@ -422,7 +581,10 @@ mod tests {
 
         // Assert
         assert!(result);
-        assert_eq!(1, header.is_locked.load(Ordering::Acquire));
+        assert_eq!(
+            current_owner_value(),
+            header.is_locked.load(Ordering::Acquire)
+        );
     }
 
     #[test]
@@ -436,7 +598,10 @@ mod tests {
 
         // Assert
         assert!(!result);
-        assert_eq!(1, header.is_locked.load(Ordering::Acquire));
+        assert_eq!(
+            current_owner_value(),
+            header.is_locked.load(Ordering::Acquire)
+        );
     }
 
     #[test]
@@ -448,7 +613,40 @@ mod tests {
         header.lock();
 
         // Assert
-        assert_eq!(1, header.is_locked.load(Ordering::Acquire));
+        assert_eq!(
+            current_owner_value(),
+            header.is_locked.load(Ordering::Acquire)
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "windows"))]
+    fn lock_should_recover_lock_held_by_dead_thread() {
+        // Arrange
+        let mut header = LocatorHeader::new();
+        let header_address = &mut header as *mut LocatorHeader as usize;
+        std::thread::spawn(move || unsafe { (*(header_address as *mut LocatorHeader)).lock() })
+            .join()
+            .unwrap();
+        assert_ne!(0, header.is_locked.load(Ordering::Acquire));
+
+        // Act
+        header.lock();
+
+        // Assert
+        assert_eq!(
+            current_owner_value(),
+            header.is_locked.load(Ordering::Acquire)
+        );
+    }
+
+    #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "Attempted to re-lock LocatorHeader")]
+    fn lock_should_panic_when_already_held_by_current_thread() {
+        let mut header = LocatorHeader::new();
+        header.lock();
+        header.lock();
     }
 
     #[test]
@@ -587,13 +785,16 @@ mod tests {
         let header = unsafe { &mut *header_ptr };
         header.initialize(LENGTH);
 
-        let size = 100;
-        let min_address = get_sys_info().max_address / 2;
-        let max_address = get_sys_info().max_address;
+        let settings = BufferSearchSettings {
+            min_address: get_sys_info().max_address / 2,
+            max_address: get_sys_info().max_address,
+            size: 100,
+            ..BufferSearchSettings::new()
+        };
 
         // Act
         let item_count = header.num_items;
-        let result = header.try_allocate_item(size, min_address, max_address);
+        let result = header.try_allocate_item(&settings);
         assert_eq!(item_count + 1, header.num_items);
 
         // Assert
@@ -601,8 +802,8 @@ mod tests {
         unsafe {
             let item = result.unwrap_unchecked();
             let address = (*item.item.get()).base_address.value;
-            assert!(address >= min_address);
-            assert!(address <= max_address);
+            assert!(address >= settings.min_address);
+            assert!(address <= settings.max_address);
         }
     }
 
@@ -616,13 +817,16 @@ mod tests {
         header.initialize(LENGTH);
         header.num_items = MAX_ITEM_COUNT as u8;
 
-        let size = 100;
-        let min_address = get_sys_info().max_address / 2;
-        let max_address = get_sys_info().max_address;
+        let settings = BufferSearchSettings {
+            min_address: get_sys_info().max_address / 2,
+            max_address: get_sys_info().max_address,
+            size: 100,
+            ..BufferSearchSettings::new()
+        };
 
         // Act
         let item_count = header.num_items;
-        let result = header.try_allocate_item(size, min_address, max_address);
+        let result = header.try_allocate_item(&settings);
         assert_eq!(item_count, header.num_items);
 
         // Assert
@@ -638,14 +842,51 @@ mod tests {
         let header = unsafe { &mut *header_ptr };
         header.initialize(LENGTH);
 
-        let size = 100;
-        let min_address = 0;
-        let max_address = 10; // Set maxAddress to a small value to make allocation impossible
+        let settings = BufferSearchSettings {
+            min_address: 0,
+            max_address: 10,
+            size: 100,
+            ..BufferSearchSettings::new()
+        }; // Set maxAddress to a small value to make allocation impossible
+
+        // Act
+        assert!(header.try_allocate_item(&settings).is_err());
+    }
+
+    #[test]
+    #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+    fn initialize_should_not_overlap_buffers() {
+        // Arrange
+        let offset = get_buffers_offset();
+        let chunk_length = get_preallocated_chunk_length() as usize;
+        let length = offset + chunk_length * 2 + 100;
+        let layout = Layout::from_size_align(length, get_sys_info().page_size as usize).unwrap();
+        let ptr = unsafe { alloc(layout) };
+        let header = unsafe { &mut *(ptr as *mut LocatorHeader) };
 
         // Act
-        assert!(header
-            .try_allocate_item(size, min_address, max_address)
-            .is_err());
+        header.initialize(length);
+
+        // Assert
+        assert_eq!(header.num_items, 3);
+        unsafe {
+            for (index, (offset, size)) in [
+                (offset, chunk_length),
+                (offset + chunk_length, chunk_length),
+                (offset + chunk_length * 2, 100),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let item = &*header.get_item(index);
+                let base_address = item.base_address.value;
+                let item_size = item.size;
+                assert_eq!(base_address, ptr as usize + offset);
+                assert_eq!(item_size as usize, size);
+            }
+
+            dealloc(ptr, layout);
+        }
     }
 
     #[test]
@@ -666,4 +907,31 @@ mod tests {
         assert_eq!(next as usize, next_cached as usize);
         assert_ne!(next as usize, 0);
     }
+
+    #[test]
+    fn get_next_locator_near_should_allocate_within_range() {
+        // Arrange
+        let ptr =
+            unsafe { alloc(Layout::from_size_align(LENGTH, align_of::<LocatorHeader>()).unwrap()) };
+        let header_ptr = ptr as *mut LocatorHeader;
+        let header = unsafe { &mut *header_ptr };
+        header.initialize(LENGTH);
+        header.num_items = MAX_ITEM_COUNT as u8;
+
+        let min_address = get_sys_info().max_address / 2;
+        let max_address = get_sys_info().max_address;
+
+        // Act
+        let next = header
+            .get_next_locator_near(min_address, max_address)
+            .unwrap();
+
+        // Assert
+        assert!(next as usize >= min_address);
+        assert!(next as usize <= max_address);
+        unsafe {
+            let this_address = (*next).this_address.value;
+            assert_eq!(this_address, next);
+        }
+    }
 }
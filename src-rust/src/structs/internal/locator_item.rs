@@ -1,13 +1,21 @@
-﻿use crate::utilities::disable_write_xor_execute::{
-    disable_write_xor_execute, restore_write_xor_execute,
+use crate::utilities::disable_write_xor_execute::{
+    disable_write_xor_execute, restore_write_xor_execute, DisableWriteXorExecuteGuard,
 };
 use crate::utilities::icache_clear::clear_instruction_cache;
-use crate::utilities::mathematics::add_with_overflow_cap;
+use crate::utilities::lock_owner::{current_owner_value, owner_thread_id, try_recover};
+use crate::utilities::mathematics::{add_with_overflow_cap, round_up};
 use crate::utilities::wrappers::Unaligned;
-use core::mem::size_of;
+use core::mem::{size_of, size_of_val};
 use core::ptr::copy_nonoverlapping;
+use core::slice::from_raw_parts_mut;
 use core::sync::atomic::{AtomicI32, Ordering};
 
+#[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+use {
+    crate::utilities::lock_diagnostics::{on_acquired, on_released, LockWait},
+    core::panic::Location,
+};
+
 /// Individual item in the locator.
 #[repr(C)]
 pub struct LocatorItem {
@@ -60,20 +68,68 @@ impl LocatorItem {
     /// Returns true if the current item is locked, else false.
     pub fn is_taken(&self) -> bool {
         let result = self.is_taken.load(Ordering::SeqCst);
-        result == 1
+        result != 0
+    }
+
+    /// Returns the ID of the thread which holds the lock on this item.
+    ///
+    /// Returns `None` if the item is not locked, or if the owner is unknown
+    /// (e.g. it was locked by an older version of the library).
+    pub fn owner_thread_id(&self) -> Option<u32> {
+        owner_thread_id(self.is_taken.load(Ordering::SeqCst))
     }
 
     /// Tries to acquire the lock.
     ///
     /// Returns true if the lock was successfully acquired, false otherwise.
+    ///
+    /// # Remarks
+    ///
+    /// If the lock is held by a thread which no longer exists, the lock is taken over.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn try_lock(&mut self) -> bool {
-        let was_taken = self.is_taken.swap(1, Ordering::SeqCst);
-        was_taken == 0
+        let owner = current_owner_value();
+        match self
+            .is_taken
+            .compare_exchange(0, owner, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => {
+                #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+                on_acquired(self as *const Self as usize, Location::caller());
+                true
+            }
+            Err(current) => {
+                let recovered = try_recover(&self.is_taken, current, owner);
+
+                #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+                if recovered {
+                    on_acquired(self as *const Self as usize, Location::caller());
+                }
+
+                recovered
+            }
+        }
     }
 
     /// Acquires the lock, blocking until it can do so.
+    ///
+    /// # Panics
+    ///
+    /// With the `lock_diagnostics` feature in debug builds, if the lock is already held by the
+    /// current thread. Waits exceeding a threshold are also reported to stderr.
+    #[cfg_attr(all(feature = "lock_diagnostics", debug_assertions), track_caller)]
     pub fn lock(&mut self) {
+        #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+        let mut wait = LockWait::new(
+            "LocatorItem",
+            self as *const Self as usize,
+            self.is_taken.load(Ordering::SeqCst),
+        );
+
         while !self.try_lock() {
+            #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+            wait.on_spin(self.is_taken.load(Ordering::SeqCst));
+
             #[cfg(all(feature = "std", not(unix), not(windows)))]
             {
                 std::thread::yield_now();
@@ -93,6 +149,9 @@ impl LocatorItem {
 
     /// Unlocks the object in a thread-safe manner.
     pub fn unlock(&mut self) {
+        #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+        on_released(self as *const Self as usize);
+
         // Need to amend C API if we ever need to do anything more here, since it forgets item.
         self.is_taken.store(0, Ordering::SeqCst);
     }
@@ -125,6 +184,21 @@ impl LocatorItem {
         start_available_address >= min_address && end_available_address <= max_address
     }
 
+    /// Advances the current position to the next address aligned to `alignment`.
+    ///
+    /// # Arguments
+    ///
+    /// * `alignment` - Required alignment, a power of 2.
+    ///
+    /// # Remarks
+    ///
+    /// Skipped bytes are left as they are. It is the caller's responsibility to ensure there
+    /// is sufficient space in the buffer.
+    pub fn align_position(&mut self, alignment: usize) {
+        let address = self.base_address.value + self.position as usize;
+        self.position += (round_up(address, alignment) - address) as u32;
+    }
+
     /// Appends the code to this buffer.
     ///
     /// # Arguments
@@ -158,6 +232,50 @@ impl LocatorItem {
         address
     }
 
+    /// Appends code which depends on its own address (e.g. relative branches) to this buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `emit` - Called with the address the code will be written to, and the remaining space in the
+    ///   buffer to write it in. Returns the number of bytes written.
+    ///
+    /// # Returns
+    ///
+    /// The address of the written data.
+    ///
+    /// # Remarks
+    ///
+    /// Write protection is lifted and the instruction cache is cleared once, for all written bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `emit` returns a length larger than the space it was given.
+    ///
+    /// # Safety
+    ///
+    /// The remaining space in the buffer must not be in use (e.g. executed) by anyone else.
+    pub unsafe fn append_code_with<F>(&mut self, emit: F) -> usize
+    where
+        F: FnOnce(usize, &mut [u8]) -> usize,
+    {
+        let address = self.base_address.value + self.position as usize;
+        let available = self.bytes_left() as usize;
+
+        // Restores protection even if `emit` panics.
+        let guard = DisableWriteXorExecuteGuard::new(address as *const u8, available);
+        let data_len = emit(address, from_raw_parts_mut(address as *mut u8, available));
+        drop(guard);
+
+        assert!(
+            data_len <= available,
+            "emitted more bytes than available in the buffer"
+        );
+
+        self.position += data_len as u32;
+        clear_instruction_cache(address as *mut u8, (address + data_len) as *mut u8);
+        address
+    }
+
     /// Appends the data to this buffer.
     ///
     /// # Arguments
@@ -220,6 +338,39 @@ impl LocatorItem {
         restore_write_xor_execute(self.base_address.value as *const u8, size_of::<T>());
         address as usize
     }
+
+    /// Appends the items to this buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The items to append to the buffer.
+    ///
+    /// # Returns
+    ///
+    /// The address of the written data.
+    ///
+    /// # Remarks
+    ///
+    /// The items are copied as `T` (not as bytes), so any padding within them is never read.
+    /// It is the caller's responsibility to ensure there is sufficient space in the buffer,
+    /// and that the current position is suitably aligned for `T`.
+    ///
+    /// # Safety
+    ///
+    /// Uses raw pointers, thus is technically unsafe.
+    pub unsafe fn append_slice<T>(&mut self, data: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let address = self.base_address.value + self.position as usize;
+        let data_len = size_of_val(data);
+
+        disable_write_xor_execute(address as *const u8, data_len);
+        copy_nonoverlapping(data.as_ptr(), address as *mut T, data.len());
+        self.position += data_len as u32;
+        restore_write_xor_execute(address as *const u8, data_len);
+        address
+    }
 }
 
 #[cfg(test)]
@@ -228,6 +379,69 @@ mod tests {
     use memoffset::offset_of;
     use std::mem::size_of;
 
+    #[test]
+    fn align_position_should_skip_to_aligned_address() {
+        let mut item = LocatorItem::new(0x1001, 64);
+        item.align_position(8);
+        assert_eq!(item.position, 7);
+
+        item.align_position(8);
+        assert_eq!(item.position, 7);
+    }
+
+    #[test]
+    fn append_code_with_should_pass_destination_address() {
+        let mut data = [0u8; 16];
+        let mut item = LocatorItem::new(data.as_mut_ptr() as usize, data.len() as u32);
+        item.position = 4;
+
+        let expected = data.as_ptr() as usize + 4;
+        let address = unsafe {
+            item.append_code_with(|address, buffer| {
+                assert_eq!(address, expected);
+                assert_eq!(buffer.len(), 12);
+                buffer[..2].copy_from_slice(&[0xAB, 0xCD]);
+                2
+            })
+        };
+
+        assert_eq!(address, expected);
+        assert_eq!(item.position, 6);
+        assert_eq!(data[4..6], [0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn append_code_with_should_not_advance_when_emit_panics() {
+        let mut data = [0u8; 16];
+        let mut item = LocatorItem::new(data.as_mut_ptr() as usize, data.len() as u32);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            item.append_code_with(|_, _| panic!("emit failed"))
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(item.position, 0);
+    }
+
+    #[test]
+    fn append_slice_should_copy_padded_items() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Padded {
+            a: u8,
+            b: u32,
+        }
+
+        let mut data = [0u32; 8];
+        let mut item = LocatorItem::new(data.as_mut_ptr() as usize, size_of_val(&data) as u32);
+        let items = [Padded { a: 1, b: 2 }, Padded { a: 3, b: 4 }];
+
+        let address = unsafe { item.append_slice(&items) };
+
+        assert_eq!(address, data.as_ptr() as usize);
+        assert_eq!(item.position as usize, size_of_val(&items));
+        assert_eq!(unsafe { *(address as *const [Padded; 2]) }, items);
+    }
+
     #[test]
     fn is_correct_size() {
         assert_eq!(0, offset_of!(LocatorItem, base_address));
@@ -279,6 +493,63 @@ mod tests {
         assert!(item.is_taken());
     }
 
+    #[test]
+    fn lock_should_store_current_thread_as_owner() {
+        // Arrange
+        let mut item = LocatorItem::new(0, 0);
+
+        // Act
+        item.lock();
+
+        // Assert
+        assert_eq!(current_owner_value(), item.is_taken.load(Ordering::SeqCst));
+        assert_eq!(
+            owner_thread_id(current_owner_value()),
+            item.owner_thread_id()
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "windows"))]
+    fn lock_should_recover_lock_held_by_dead_thread() {
+        // Arrange
+        let mut item = LocatorItem::new(0, 0);
+        let item_address = &mut item as *mut LocatorItem as usize;
+        std::thread::spawn(move || unsafe { (*(item_address as *mut LocatorItem)).lock() })
+            .join()
+            .unwrap();
+        assert!(item.is_taken());
+
+        // Act
+        item.lock();
+
+        // Assert
+        assert_eq!(current_owner_value(), item.is_taken.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn try_lock_should_not_recover_lock_with_unknown_owner() {
+        // Arrange
+        let mut item = LocatorItem::new(0, 0);
+        item.is_taken.store(1, Ordering::SeqCst);
+
+        // Act
+        let result = item.try_lock();
+
+        // Assert
+        assert!(!result);
+        assert_eq!(1, item.is_taken.load(Ordering::SeqCst));
+    }
+
+    #[cfg(all(feature = "lock_diagnostics", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "Attempted to re-lock LocatorItem")]
+    fn lock_should_panic_when_already_held_by_current_thread() {
+        let mut item = LocatorItem::new(0, 0);
+        item.lock();
+        item.lock();
+    }
+
     #[test]
     fn unlock_should_release_lock_when_item_is_locked() {
         // Arrange
@@ -0,0 +1,242 @@
+extern crate alloc;
+use crate::structs::MemoryPermissions;
+use crate::utilities::cached::get_sys_info;
+use crate::utilities::linux_map_parser::{parse_memory_map_region, LineReader, ProcFile};
+use crate::utilities::map_parser_utilities::MemoryMapEntryTrait;
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+/// A single mapping in the memory map of a process, i.e. one line of `/proc/<pid>/maps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMapRegion {
+    /// Address of the first byte of the region.
+    pub start_address: usize,
+
+    /// Address of the byte after the last byte of the region.
+    pub end_address: usize,
+
+    pub permissions: MemoryPermissions,
+
+    /// Offset into the mapped file; 0 for anonymous memory.
+    pub offset: u64,
+
+    /// Major number of the device holding the mapped file.
+    pub device_major: u32,
+
+    /// Minor number of the device holding the mapped file.
+    pub device_minor: u32,
+
+    /// Inode of the mapped file; 0 for anonymous memory.
+    pub inode: u64,
+
+    /// Path of the mapped file, or a pseudo path such as `[stack]`, `[heap]` or `[vdso]`.
+    /// `None` for anonymous memory.
+    pub path: Option<String>,
+}
+
+impl MemoryMapRegion {
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.end_address - self.start_address
+    }
+
+    /// Returns true if the given address lies within this region.
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.start_address && address < self.end_address
+    }
+
+    /// Returns the file name of the mapped file, e.g. `libc.so.6` for `/usr/lib/libc.so.6`.
+    pub fn file_name(&self) -> Option<&str> {
+        let path = self.path.as_deref()?;
+        Some(path.rsplit('/').next().unwrap_or(path))
+    }
+}
+
+impl MemoryMapEntryTrait for MemoryMapRegion {
+    fn start_address(&self) -> usize {
+        self.start_address
+    }
+
+    fn end_address(&self) -> usize {
+        self.end_address
+    }
+}
+
+/// The memory map of a process, as reported by `/proc/<pid>/maps`.
+///
+/// # Remarks
+///
+/// This is a snapshot; the process may map or unmap memory at any time after it is read.
+/// Only available on Linux.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryMap {
+    /// All mapped regions, sorted by address.
+    pub regions: Vec<MemoryMapRegion>,
+}
+
+impl MemoryMap {
+    /// Reads the memory map of the current process.
+    ///
+    /// # Returns
+    ///
+    /// The memory map, or the `errno` if it could not be read.
+    pub fn from_current_process() -> Result<Self, i32> {
+        Self::from_process_id(get_sys_info().this_process_id)
+    }
+
+    /// Reads the memory map of the given process.
+    ///
+    /// # Arguments
+    ///
+    /// * `process_id` - ID of the process to read the memory map of.
+    ///
+    /// # Returns
+    ///
+    /// The memory map, or the `errno` if it could not be read (e.g. `ENOENT` if the process
+    /// does not exist, or `EACCES` if we lack permission).
+    pub fn from_process_id(process_id: u32) -> Result<Self, i32> {
+        let maps = ProcFile::open_maps(process_id as i32)?;
+        let mut lines = LineReader::new(maps);
+        let mut regions = Vec::new();
+        while let Some(line) = lines.next_line() {
+            let region = core::str::from_utf8(line?)
+                .ok()
+                .and_then(parse_memory_map_region);
+            regions.extend(region);
+        }
+
+        Ok(Self { regions })
+    }
+
+    /// Parses a memory map in the format of `/proc/<pid>/maps`. Invalid lines are skipped.
+    pub fn parse(text: &str) -> Self {
+        Self {
+            regions: text.lines().filter_map(parse_memory_map_region).collect(),
+        }
+    }
+
+    /// Returns the region containing the given address.
+    pub fn region_at(&self, address: usize) -> Option<&MemoryMapRegion> {
+        let index = self
+            .regions
+            .partition_point(|region| region.end_address <= address);
+
+        self.regions
+            .get(index)
+            .filter(|region| region.contains(address))
+    }
+
+    /// Returns the permissions of the memory at the given address, or `None` if it is not mapped.
+    pub fn protection_at(&self, address: usize) -> Option<MemoryPermissions> {
+        self.region_at(address).map(|region| region.permissions)
+    }
+
+    /// Finds a module (mapped file) by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Either the full path of the file, or its file name (e.g. `libc.so.6`).
+    ///
+    /// # Returns
+    ///
+    /// The start and end address of the module, spanning all of its mapped regions;
+    /// or `None` if it is not mapped.
+    pub fn find_module(&self, name: &str) -> Option<(usize, usize)> {
+        let is_module = |region: &&MemoryMapRegion| {
+            region.path.as_deref() == Some(name) || region.file_name() == Some(name)
+        };
+
+        let first = self.regions.iter().find(is_module)?;
+        let last = self.regions.iter().rfind(is_module)?;
+        Some((first.start_address, last.end_address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+555555554000-555555556000 r--p 00000000 08:01 1234                       /usr/bin/game
+555555556000-55555555a000 r-xp 00002000 08:01 1234                       /usr/bin/game
+55555555a000-55555555c000 rw-p 00006000 08:01 1234                       /usr/bin/game
+55555555c000-55555557d000 rw-p 00000000 00:00 0                          [heap]
+7ffff7dd3000-7ffff7dd5000 rw-s 00000000 00:05 98                         /dev/shm/Reloaded.Memory.Buffers.MemoryBuffer, PID 42
+7ffff7dd5000-7ffff7dd7000 rw-p 00000000 00:00 0
+";
+
+    #[test]
+    fn parse_should_read_all_fields() {
+        let map = MemoryMap::parse(MAPS);
+        assert_eq!(map.regions.len(), 6);
+
+        let region = &map.regions[1];
+        assert_eq!(region.start_address, 0x555555556000);
+        assert_eq!(region.end_address, 0x55555555a000);
+        assert_eq!(
+            region.permissions,
+            MemoryPermissions {
+                read: true,
+                write: false,
+                execute: true,
+                shared: false
+            }
+        );
+        assert_eq!(region.offset, 0x2000);
+        assert_eq!(region.device_major, 8);
+        assert_eq!(region.device_minor, 1);
+        assert_eq!(region.inode, 1234);
+        assert_eq!(region.path.as_deref(), Some("/usr/bin/game"));
+    }
+
+    #[test]
+    fn parse_should_handle_paths_with_spaces_and_anonymous_memory() {
+        let map = MemoryMap::parse(MAPS);
+        assert_eq!(
+            map.regions[4].path.as_deref(),
+            Some("/dev/shm/Reloaded.Memory.Buffers.MemoryBuffer, PID 42")
+        );
+        assert!(map.regions[4].permissions.shared);
+        assert_eq!(map.regions[5].path, None);
+    }
+
+    #[test]
+    fn find_module_should_span_all_regions() {
+        let map = MemoryMap::parse(MAPS);
+        let expected = Some((0x555555554000, 0x55555555c000));
+        assert_eq!(map.find_module("game"), expected);
+        assert_eq!(map.find_module("/usr/bin/game"), expected);
+        assert_eq!(
+            map.find_module("[heap]"),
+            Some((0x55555555c000, 0x55555557d000))
+        );
+        assert_eq!(map.find_module("missing"), None);
+    }
+
+    #[test]
+    fn protection_at_should_return_region_permissions() {
+        let map = MemoryMap::parse(MAPS);
+        assert!(map.protection_at(0x555555557000).unwrap().execute);
+        assert!(!map.protection_at(0x555555554000).unwrap().execute);
+        assert!(map.protection_at(0x55555555bfff).unwrap().write);
+        assert_eq!(map.protection_at(0x7ffff7dd7000), None);
+        assert_eq!(map.protection_at(0), None);
+    }
+
+    #[test]
+    fn from_current_process_should_contain_own_code() {
+        let map = MemoryMap::from_current_process().unwrap();
+        let address = from_current_process_should_contain_own_code as *const () as usize;
+        let permissions = map.protection_at(address).unwrap();
+        assert!(permissions.read && permissions.execute);
+    }
+
+    #[test]
+    fn from_process_id_should_return_errno_for_missing_process() {
+        assert_eq!(
+            MemoryMap::from_process_id(i32::MAX as u32),
+            Err(libc::ENOENT)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+/// Access permissions of a mapped memory region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryPermissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+
+    /// True if the mapping is shared with other processes, false if it is private (copy on write).
+    pub shared: bool,
+}
+
+impl MemoryPermissions {
+    /// Private read, write and execute permissions; used for all buffers.
+    pub const READ_WRITE_EXECUTE: Self = Self {
+        read: true,
+        write: true,
+        execute: true,
+        shared: false,
+    };
+
+    /// Parses permissions in the format used by `/proc/<pid>/maps`, e.g. `r-xp`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let bytes = text.as_bytes();
+        if bytes.len() != 4 {
+            return None;
+        }
+
+        Some(Self {
+            read: bytes[0] == b'r',
+            write: bytes[1] == b'w',
+            execute: bytes[2] == b'x',
+            shared: bytes[3] == b's',
+        })
+    }
+}
@@ -0,0 +1,16 @@
+/// Where within the allowed address range the buffer allocator should prefer to place new allocations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub enum AllocationPlacement {
+    /// Lowest available address.
+    #[default]
+    Lowest,
+
+    /// Highest available address.
+    Highest,
+
+    /// Available address closest to [`BufferAllocatorSettings::target_address`].
+    ///
+    /// [`BufferAllocatorSettings::target_address`]: crate::structs::params::BufferAllocatorSettings::target_address
+    Nearest,
+}
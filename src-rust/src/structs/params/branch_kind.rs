@@ -0,0 +1,149 @@
+/// Kind of instruction which references an address relative to its own; e.g. a branch from a hook's
+/// target into a buffer, or from a buffer back to the target.
+///
+/// # Remarks
+///
+/// Each kind has a limited, signed reach encoded in the instruction. Use with
+/// [`BufferSearchSettings::from_branch`] to get a buffer within reach of a target.
+///
+/// [`BufferSearchSettings::from_branch`]: crate::structs::params::BufferSearchSettings::from_branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum BranchKind {
+    /// x86/x86_64 `jmp`/`call`/`jcc` with a 32-bit displacement, and RIP-relative operands (`rel32`).
+    /// Signed 32-bit offset from the end of the instruction; about ±2GiB.
+    X86Rel32,
+
+    /// aarch64 `B`/`BL`. Signed 26-bit offset in units of 4 bytes; ±128MiB.
+    Arm64Branch,
+
+    /// aarch64 `ADRP`. Signed 21-bit offset in units of 4KiB pages, from the instruction's page; ±4GiB.
+    Arm64Adrp,
+
+    /// aarch64 `LDR` (literal), `ADR` and conditional branches (`B.cond`, `CBZ`).
+    /// Signed 19-bit offset in units of 4 bytes; ±1MiB.
+    Arm64LdrLiteral,
+}
+
+/// Maximum length of an x86 instruction.
+const X86_MAX_INSTRUCTION_LENGTH: i128 = 15;
+
+/// Size of the pages `ADRP` addresses.
+const ARM64_ADRP_PAGE_SIZE: i128 = 4096;
+
+impl BranchKind {
+    /// Returns the maximum distance between two addresses such that an instruction at either
+    /// one can always reach the other.
+    pub const fn max_distance(self) -> usize {
+        match self {
+            // Displacement is relative to the end of the instruction, which may be up to 15 bytes past it.
+            BranchKind::X86Rel32 => (1 << 31) - X86_MAX_INSTRUCTION_LENGTH as usize,
+            BranchKind::Arm64Branch => (1 << 27) - 4,
+
+            // Distances are rounded to pages; so leave a page of room for either address' offset in its page.
+            BranchKind::Arm64Adrp => u32::MAX as usize - (2 * ARM64_ADRP_PAGE_SIZE as usize - 1),
+            BranchKind::Arm64LdrLiteral => (1 << 20) - 4,
+        }
+    }
+
+    /// Returns the alignment instructions of this kind (and their targets) must have.
+    pub const fn instruction_alignment(self) -> usize {
+        match self {
+            BranchKind::X86Rel32 => 1,
+            BranchKind::Arm64Branch | BranchKind::Arm64Adrp | BranchKind::Arm64LdrLiteral => 4,
+        }
+    }
+
+    /// Returns true if an instruction of this kind at `source` can reference `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Address of the instruction.
+    /// * `target` - Address the instruction should reference.
+    ///
+    /// # Remarks
+    ///
+    /// For [`BranchKind::X86Rel32`], the offset is relative to the end of the instruction; this
+    /// accounts for any instruction length (up to 15 bytes).
+    pub fn can_reach(self, source: usize, target: usize) -> bool {
+        let offset = target as i128 - source as i128;
+        match self {
+            BranchKind::X86Rel32 => {
+                // Offset from the end of the shortest and longest possible instruction.
+                offset - 1 <= i32::MAX as i128
+                    && offset - X86_MAX_INSTRUCTION_LENGTH >= i32::MIN as i128
+            }
+            BranchKind::Arm64Branch => Self::fits_scaled(offset, 26, 4),
+            BranchKind::Arm64Adrp => {
+                let page_offset = (target as i128 & !(ARM64_ADRP_PAGE_SIZE - 1))
+                    - (source as i128 & !(ARM64_ADRP_PAGE_SIZE - 1));
+                Self::fits_scaled(page_offset, 21, ARM64_ADRP_PAGE_SIZE)
+            }
+            BranchKind::Arm64LdrLiteral => Self::fits_scaled(offset, 19, 4),
+        }
+    }
+
+    /// Returns true if `offset` is a multiple of `scale`, which fits in a signed immediate of `bits` bits
+    /// after dividing by `scale`.
+    fn fits_scaled(offset: i128, bits: u32, scale: i128) -> bool {
+        let limit = (1i128 << (bits - 1)) * scale;
+        offset % scale == 0 && offset >= -limit && offset < limit
+    }
+}
+
+#[cfg(all(test, target_pointer_width = "64"))]
+mod tests {
+    use super::*;
+
+    const BASE: usize = 0x10_0000_0000;
+
+    #[test]
+    fn x86_rel32_should_respect_displacement_range() {
+        let kind = BranchKind::X86Rel32;
+        assert!(kind.can_reach(BASE, BASE + i32::MAX as usize));
+        assert!(!kind.can_reach(BASE, BASE + i32::MAX as usize + 2));
+        assert!(kind.can_reach(BASE, BASE - (1 << 31) + 15));
+        assert!(!kind.can_reach(BASE, BASE - (1 << 31)));
+    }
+
+    #[test]
+    fn arm64_branch_should_be_asymmetric_and_aligned() {
+        let kind = BranchKind::Arm64Branch;
+        assert!(kind.can_reach(BASE, BASE - (1 << 27)));
+        assert!(!kind.can_reach(BASE, BASE - (1 << 27) - 4));
+        assert!(kind.can_reach(BASE, BASE + (1 << 27) - 4));
+        assert!(!kind.can_reach(BASE, BASE + (1 << 27)));
+        assert!(!kind.can_reach(BASE, BASE + 2));
+    }
+
+    #[test]
+    fn arm64_adrp_should_compare_pages() {
+        let kind = BranchKind::Arm64Adrp;
+        assert!(kind.can_reach(BASE + 0xFFF, BASE + (1 << 32) - 0x1000));
+        assert!(!kind.can_reach(BASE, BASE + (1 << 32)));
+        assert!(kind.can_reach(BASE + 0xFFF, BASE - (1 << 32)));
+        assert!(!kind.can_reach(BASE, BASE - (1 << 32) - 1));
+    }
+
+    #[test]
+    fn arm64_ldr_literal_should_respect_range() {
+        let kind = BranchKind::Arm64LdrLiteral;
+        assert!(kind.can_reach(BASE, BASE + (1 << 20) - 4));
+        assert!(!kind.can_reach(BASE, BASE + (1 << 20)));
+        assert!(kind.can_reach(BASE, BASE - (1 << 20)));
+    }
+
+    #[test]
+    fn max_distance_should_be_reachable_in_both_directions() {
+        for kind in [
+            BranchKind::X86Rel32,
+            BranchKind::Arm64Branch,
+            BranchKind::Arm64Adrp,
+            BranchKind::Arm64LdrLiteral,
+        ] {
+            let distance = kind.max_distance();
+            assert!(kind.can_reach(BASE, BASE + distance), "{:?}", kind);
+            assert!(kind.can_reach(BASE + distance, BASE), "{:?}", kind);
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use crate::structs::params::{AllocationPlacement, BranchKind};
 use crate::utilities::{cached::get_sys_info, mathematics};
+
+#[cfg(target_os = "linux")]
+use crate::utilities::linux_huge_pages::get_huge_page_size;
 use core::cmp::max;
 
 /// Settings to pass to the buffer allocator.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct BufferAllocatorSettings {
     /// Minimum address of the allocation.
@@ -33,8 +37,51 @@ pub struct BufferAllocatorSettings {
     /// (prior to rewrite) by the Dalamud folks. In Wine and on FFXIV *only*; this was ever the case.
     /// Inclusion of a brute force approach is a last ditch workaround for that.
     ///
-    /// This setting is only used on Windows targets today.
+    /// On Windows, every allocation granularity aligned address between `min_address` and `max_address`
+    /// is queried. On Linux, every allocation granularity aligned address within each free region is tried,
+    /// up to a limit on the number of attempts. Other platforms ignore this setting.
     pub brute_force: bool,
+
+    /// Where within the allowed address range new allocations should preferably be placed.
+    ///
+    /// # Remarks
+    ///
+    /// Only used on Linux and platforms using the generic (mmap-rs) allocator today.
+    /// Windows and macOS always prefer the lowest available address.
+    pub placement: AllocationPlacement,
+
+    /// Address to allocate close to, when `placement` is [`AllocationPlacement::Nearest`].
+    pub target_address: usize,
+
+    /// Whether the allocation should be backed by huge pages.
+    ///
+    /// # Remarks
+    ///
+    /// Only used on Linux today. Explicit huge pages (`MAP_HUGETLB`) are used if the system has them
+    /// reserved, otherwise transparent huge pages are requested. The allocation is aligned to, and
+    /// its size rounded up to the huge page size.
+    ///
+    /// Huge pages are not guaranteed; check [`PrivateAllocation::page_size`] for the page size that was
+    /// actually obtained.
+    ///
+    /// [`PrivateAllocation::page_size`]: crate::structs::PrivateAllocation::page_size
+    pub huge_pages: bool,
+
+    /// Alignment of the allocation's address; its size is also rounded up to this.
+    ///
+    /// # Remarks
+    ///
+    /// Defaults to the system's allocation granularity. Only used on Linux today, where that is the page
+    /// size; so candidate addresses already fit into gaps a single page wide, e.g. a small window right
+    /// next to a module. It can be raised to keep allocations coarsely aligned (e.g. to 64K, matching
+    /// Windows). Other platforms always use the system's allocation granularity (on Windows,
+    /// `VirtualAlloc` requires it).
+    ///
+    /// Sanitized to a power of 2, no smaller than the page size (or huge page size with `huge_pages`).
+    /// Values above 2^31 are rejected with [`BufferAllocationErrorKind::InvalidSettings`].
+    ///
+    /// [`BufferAllocationErrorKind::InvalidSettings`]: crate::structs::errors::BufferAllocationErrorKind::InvalidSettings
+    pub allocation_granularity: u32,
 }
 
 impl BufferAllocatorSettings {
@@ -48,10 +95,15 @@ impl BufferAllocatorSettings {
             target_process_id: sys_info.this_process_id,
             retry_count: 8,
             brute_force: true,
+            placement: AllocationPlacement::Lowest,
+            target_address: 0,
+            huge_pages: false,
+            allocation_granularity: sys_info.allocation_granularity as u32,
         }
     }
 
-    /// Creates settings such that the returned buffer will always be within `proximity` bytes of `target`.
+    /// Creates settings such that the returned buffer will always be within `proximity` bytes of `target`,
+    /// preferring the free space closest to `target`.
     ///
     /// # Arguments
     ///
@@ -67,11 +119,34 @@ impl BufferAllocatorSettings {
             max_address: mathematics::add_with_overflow_cap(target, proximity),
             min_address: mathematics::subtract_with_underflow_cap(target, proximity),
             size: size as u32,
+            placement: AllocationPlacement::Nearest,
+            target_address: target,
             ..Self::new()
         }
     }
 
+    /// Creates settings such that the returned buffer will always be within reach of `target` for the
+    /// given kind of branch or reference, in either direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of instruction which references `target` from the buffer, or the buffer from `target`.
+    /// * `target` - Target address.
+    /// * `size` - Size required in the settings.
+    ///
+    /// # Returns
+    ///
+    /// * `BufferAllocatorSettings` - Settings that would satisfy this search.
+    pub fn from_branch(kind: BranchKind, target: usize, size: usize) -> Self {
+        Self::from_proximity(kind.max_distance(), target, size)
+    }
+
     /// Sanitizes the input values.
+    ///
+    /// # Remarks
+    ///
+    /// Values which can't be sanitized are left as they are, for the allocator to reject; i.e. an
+    /// `allocation_granularity` above 2^31, or a `size` which overflows when rounded up to it.
     pub fn sanitize(&mut self) {
         // On Windows, VirtualAlloc treats 0 as 'any address', we might aswell avoid this out the gate.
         let sys_info = get_sys_info();
@@ -79,13 +154,39 @@ impl BufferAllocatorSettings {
             self.min_address = sys_info.allocation_granularity as usize;
         }
 
-        self.size = max(self.size, 1);
-        self.size =
-            mathematics::round_up(self.size as usize, sys_info.allocation_granularity as usize)
-                as u32;
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(granularity) =
+                max(self.allocation_granularity, sys_info.page_size).checked_next_power_of_two()
+            {
+                self.allocation_granularity = granularity;
+            }
+
+            if self.huge_pages {
+                if let Some(huge_page_size) = get_huge_page_size() {
+                    self.allocation_granularity =
+                        max(self.allocation_granularity, huge_page_size as u32);
+                }
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            self.allocation_granularity = sys_info.allocation_granularity as u32;
+        }
+
+        if let Some(size) = round_up_size(max(self.size, 1), self.allocation_granularity as usize) {
+            self.size = size;
+        }
     }
 }
 
+/// Rounds `size` up to a multiple of `granularity`, returning `None` if the result doesn't fit in a `u32`.
+pub(crate) fn round_up_size(size: u32, granularity: usize) -> Option<u32> {
+    let granularity = max(granularity, 1) as u64;
+    u32::try_from((size as u64).div_ceil(granularity) * granularity).ok()
+}
+
 impl Default for BufferAllocatorSettings {
     fn default() -> Self {
         Self::new()
@@ -107,6 +208,11 @@ mod tests {
         assert_eq!(settings.target_process_id, get_sys_info().this_process_id);
         assert_eq!(settings.retry_count, 8);
         assert!(settings.brute_force);
+        assert_eq!(settings.placement, AllocationPlacement::Lowest);
+        assert_eq!(
+            settings.allocation_granularity,
+            get_sys_info().allocation_granularity as u32
+        );
     }
 
     #[test]
@@ -128,6 +234,8 @@ mod tests {
         assert_eq!(settings.target_process_id, get_sys_info().this_process_id);
         assert_eq!(settings.retry_count, 8);
         assert!(settings.brute_force);
+        assert_eq!(settings.placement, AllocationPlacement::Nearest);
+        assert_eq!(settings.target_address, target);
     }
 
     #[test]
@@ -155,4 +263,55 @@ mod tests {
             ) as u32
         );
     }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_sanitize_granularity() {
+        let page_size = get_sys_info().page_size;
+        let mut settings = BufferAllocatorSettings {
+            size: page_size + 1,
+            allocation_granularity: 1,
+            ..BufferAllocatorSettings::new()
+        };
+
+        settings.sanitize();
+        assert_eq!(settings.allocation_granularity, page_size);
+        assert_eq!(settings.size, page_size * 2);
+
+        settings.allocation_granularity = page_size * 3;
+        settings.sanitize();
+        assert_eq!(settings.allocation_granularity, page_size * 4);
+        assert_eq!(settings.size, page_size * 4);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_sanitize_should_leave_values_which_overflow() {
+        let mut settings = BufferAllocatorSettings {
+            size: u32::MAX,
+            allocation_granularity: (1 << 31) + 1,
+            ..BufferAllocatorSettings::new()
+        };
+
+        settings.sanitize();
+        assert_eq!(settings.allocation_granularity, (1 << 31) + 1);
+        assert_eq!(settings.size, u32::MAX);
+
+        settings.allocation_granularity = 1 << 31;
+        settings.sanitize();
+        assert_eq!(settings.allocation_granularity, 1 << 31);
+        assert_eq!(settings.size, u32::MAX);
+    }
+
+    #[test]
+    fn test_from_branch() {
+        let target: usize = 0x1_0000_0000;
+        let settings = BufferAllocatorSettings::from_branch(BranchKind::X86Rel32, target, 4096);
+
+        let distance = BranchKind::X86Rel32.max_distance();
+        assert_eq!(settings.min_address, target - distance);
+        assert_eq!(settings.max_address, target + distance);
+        assert_eq!(settings.placement, AllocationPlacement::Nearest);
+        assert_eq!(settings.target_address, target);
+    }
 }
@@ -0,0 +1,138 @@
+use crate::backends::MemoryBackend;
+use crate::structs::params::{BufferAllocatorSettings, BufferSearchSettings};
+use core::cmp::{max, min};
+
+/// Settings of a [`BufferManager`].
+///
+/// [`BufferManager`]: crate::buffer_manager::BufferManager
+#[derive(Clone, Copy)]
+pub struct BufferManagerSettings<'a> {
+    /// Lowest address the manager places buffers and locators at.
+    /// Searches are narrowed to start no lower.
+    pub min_address: usize,
+
+    /// Highest address the manager places buffers and locators at.
+    /// Searches are narrowed to end no higher.
+    pub max_address: usize,
+
+    /// Maximum number of locators in the manager's chain.
+    /// Once reached, new buffers can only be allocated into free slots of existing locators.
+    pub max_locators: usize,
+
+    /// Amount of times to retry allocating a new buffer.
+    /// See [`BufferAllocatorSettings::retry_count`].
+    pub retry_count: i32,
+
+    /// Whether to use brute force to find an address for a new buffer.
+    /// See [`BufferAllocatorSettings::brute_force`].
+    pub brute_force: bool,
+
+    /// Backend new buffers are allocated through, instead of the operating system.
+    ///
+    /// # Remarks
+    ///
+    /// Locators are then kept on the heap, as the manager writes to them and memory mapped through
+    /// the backend may not be accessible from this process. Requires the `alloc` feature.
+    pub backend: Option<&'a (dyn MemoryBackend + Sync)>,
+}
+
+impl<'a> BufferManagerSettings<'a> {
+    /// Initializes the manager settings with defaults; no limits, allocating from the operating system.
+    pub const fn new() -> Self {
+        Self {
+            min_address: 0,
+            max_address: usize::MAX,
+            max_locators: usize::MAX,
+            retry_count: 8,
+            brute_force: true,
+            backend: None,
+        }
+    }
+
+    /// Narrows the address range of a search to the manager's limits.
+    ///
+    /// # Returns
+    ///
+    /// The narrowed settings, or `None` if the search range is entirely outside the limits.
+    pub fn narrow_search(&self, settings: &BufferSearchSettings) -> Option<BufferSearchSettings> {
+        let mut narrowed = *settings;
+        narrowed.min_address = max(settings.min_address, self.min_address);
+        narrowed.max_address = min(settings.max_address, self.max_address);
+        (narrowed.min_address <= narrowed.max_address).then_some(narrowed)
+    }
+
+    /// Creates the settings to allocate a new buffer satisfying the given search with.
+    pub fn get_allocator_settings(
+        &self,
+        settings: &BufferSearchSettings,
+    ) -> BufferAllocatorSettings {
+        BufferAllocatorSettings {
+            min_address: settings.min_address,
+            max_address: settings.max_address,
+            size: settings.size,
+            retry_count: self.retry_count,
+            brute_force: self.brute_force,
+            placement: settings.placement,
+            target_address: settings.target_address,
+            huge_pages: settings.huge_pages,
+            ..BufferAllocatorSettings::new()
+        }
+    }
+}
+
+impl Default for BufferManagerSettings<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrow_search_should_clamp_to_limits() {
+        let manager_settings = BufferManagerSettings {
+            min_address: 0x1000,
+            max_address: 0x8000,
+            ..BufferManagerSettings::new()
+        };
+
+        let search = BufferSearchSettings {
+            min_address: 0,
+            max_address: 0x4000,
+            ..BufferSearchSettings::new()
+        };
+
+        let narrowed = manager_settings.narrow_search(&search).unwrap();
+        assert_eq!(narrowed.min_address, 0x1000);
+        assert_eq!(narrowed.max_address, 0x4000);
+
+        let outside = BufferSearchSettings {
+            min_address: 0x9000,
+            max_address: 0xA000,
+            ..BufferSearchSettings::new()
+        };
+
+        assert!(manager_settings.narrow_search(&outside).is_none());
+    }
+
+    #[test]
+    fn get_allocator_settings_should_use_manager_defaults() {
+        let manager_settings = BufferManagerSettings {
+            retry_count: 1,
+            brute_force: false,
+            ..BufferManagerSettings::new()
+        };
+
+        let search = BufferSearchSettings::from_proximity(0x1000, 0x10000, 100);
+        let settings = manager_settings.get_allocator_settings(&search);
+        assert_eq!(settings.min_address, search.min_address);
+        assert_eq!(settings.max_address, search.max_address);
+        assert_eq!(settings.size, 100);
+        assert_eq!(settings.placement, search.placement);
+        assert_eq!(settings.target_address, search.target_address);
+        assert_eq!(settings.retry_count, 1);
+        assert!(!settings.brute_force);
+    }
+}
@@ -1,7 +1,8 @@
+use crate::structs::params::{AllocationPlacement, BranchKind};
 use crate::utilities::{cached::get_sys_info, mathematics};
 
 /// Settings to pass to buffer search mechanisms.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct BufferSearchSettings {
     /// Minimum address of the allocation.
@@ -12,6 +13,19 @@ pub struct BufferSearchSettings {
 
     /// Required size of the data.
     pub size: u32,
+
+    /// Where within the allowed address range a new buffer should preferably be placed,
+    /// if no existing buffer satisfies the search.
+    pub placement: AllocationPlacement,
+
+    /// Address to allocate close to, when `placement` is [`AllocationPlacement::Nearest`].
+    pub target_address: usize,
+
+    /// Whether a new buffer should be backed by huge pages, if no existing buffer satisfies the search.
+    /// See [`BufferAllocatorSettings::huge_pages`].
+    ///
+    /// [`BufferAllocatorSettings::huge_pages`]: crate::structs::params::BufferAllocatorSettings::huge_pages
+    pub huge_pages: bool,
 }
 
 impl BufferSearchSettings {
@@ -21,10 +35,14 @@ impl BufferSearchSettings {
             min_address: 0,
             max_address: get_sys_info().max_address,
             size: 4096,
+            placement: AllocationPlacement::Lowest,
+            target_address: 0,
+            huge_pages: false,
         }
     }
 
-    /// Creates settings such that the returned buffer will always be within `proximity` bytes of `target`.
+    /// Creates settings such that the returned buffer will always be within `proximity` bytes of `target`,
+    /// preferring the free space closest to `target` when a new buffer is allocated.
     ///
     /// # Arguments
     ///
@@ -40,8 +58,27 @@ impl BufferSearchSettings {
             max_address: mathematics::add_with_overflow_cap(target, proximity),
             min_address: mathematics::subtract_with_underflow_cap(target, proximity),
             size: size as u32,
+            placement: AllocationPlacement::Nearest,
+            target_address: target,
+            huge_pages: false,
         }
     }
+
+    /// Creates settings such that the returned buffer will always be within reach of `target` for the
+    /// given kind of branch or reference, in either direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of instruction which references `target` from the buffer, or the buffer from `target`.
+    /// * `target` - Target address.
+    /// * `size` - Size required in the settings.
+    ///
+    /// # Returns
+    ///
+    /// * `BufferSearchSettings` - Settings that would satisfy this search.
+    pub fn from_branch(kind: BranchKind, target: usize, size: usize) -> Self {
+        Self::from_proximity(kind.max_distance(), target, size)
+    }
 }
 
 impl Default for BufferSearchSettings {
@@ -60,6 +97,8 @@ mod tests {
         assert_eq!(settings.min_address, 0);
         assert_eq!(settings.max_address, get_sys_info().max_address);
         assert_eq!(settings.size, 4096);
+        assert_eq!(settings.placement, AllocationPlacement::Lowest);
+        assert!(!settings.huge_pages);
     }
 
     #[test]
@@ -78,5 +117,23 @@ mod tests {
             mathematics::subtract_with_underflow_cap(target, proximity)
         );
         assert_eq!(settings.size, size as u32);
+        assert_eq!(settings.placement, AllocationPlacement::Nearest);
+        assert_eq!(settings.target_address, target);
+    }
+
+    #[test]
+    fn test_from_branch() {
+        let target: usize = 0x1_0000_0000;
+        let settings = BufferSearchSettings::from_branch(BranchKind::Arm64Branch, target, 4096);
+
+        assert_eq!(
+            settings.min_address,
+            target - BranchKind::Arm64Branch.max_distance()
+        );
+        assert_eq!(
+            settings.max_address,
+            target + BranchKind::Arm64Branch.max_distance()
+        );
+        assert_eq!(settings.size, 4096);
     }
 }
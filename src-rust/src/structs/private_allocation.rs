@@ -1,7 +1,9 @@
 use core::ptr::*;
 
-#[allow(unused_imports)]
+use crate::structs::params::BranchKind;
 use crate::utilities::cached::get_sys_info;
+use crate::utilities::disable_write_xor_execute::DisableWriteXorExecuteGuard;
+use crate::utilities::icache_clear::clear_instruction_cache;
 
 #[cfg(target_os = "windows")]
 use windows_sys::Win32::System::Memory::{VirtualFree, MEM_RELEASE};
@@ -32,6 +34,10 @@ pub struct PrivateAllocation {
 
     /// Id of the process where allocation is made.
     _this_process_id: u32,
+
+    /// Size of the pages backing the allocation.
+    /// This is larger than the system's page size if the allocation is backed by huge pages.
+    pub(crate) page_size: usize,
 }
 
 impl PrivateAllocation {
@@ -50,13 +56,16 @@ impl PrivateAllocation {
     /// # Remarks
     ///
     /// If the current process id is equal to the actual process id, it uses the local process
-    /// deallocation logic, otherwise it uses the external process deallocation logic. The external
-    /// process memory management is not implemented in this example.
+    /// deallocation logic, otherwise it uses the external process deallocation logic. External
+    /// processes are supported on Windows and Linux (x86_64), with the `external_processes` feature.
+    ///
+    /// The page size is assumed to be the system's page size.
     pub fn new(base_address: NonNull<u8>, size: usize, process_id: u32) -> Self {
         Self {
             base_address,
             size,
             _this_process_id: process_id,
+            page_size: get_sys_info().page_size as usize,
         }
     }
 
@@ -78,6 +87,84 @@ impl PrivateAllocation {
         self.size
     }
 
+    /// Gets the size of the pages backing the allocation.
+    ///
+    /// # Returns
+    ///
+    /// Returns the page size, e.g. the huge page size if the allocation was made with
+    /// [`BufferAllocatorSettings::huge_pages`] and huge pages were obtained.
+    ///
+    /// [`BufferAllocatorSettings::huge_pages`]: crate::structs::params::BufferAllocatorSettings::huge_pages
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns true if an instruction of the given kind, written at `offset` into the allocation,
+    /// can reach `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset from the start of the allocation the instruction is written to.
+    /// * `kind` - Kind of branch or reference to be written.
+    /// * `target` - Address the instruction should reference.
+    ///
+    /// # Returns
+    ///
+    /// `false` if `offset` is outside the allocation or `target` is out of reach.
+    pub fn can_reach(&self, offset: usize, kind: BranchKind, target: usize) -> bool {
+        offset < self.size && kind.can_reach(self.base_address.as_ptr() as usize + offset, target)
+    }
+
+    /// Writes data to the allocation, which may be in another process.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset from the start of the allocation to write to.
+    /// * `data` - The data to write.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the data was written, `false` if it does not fit in the allocation or the write failed.
+    ///
+    /// # Remarks
+    ///
+    /// Writing to other processes is only supported on Linux, where it uses `process_vm_writev`.
+    ///
+    /// Same as [`SafeLocatorItem::append_code`], W^X is disabled for the write and the instruction
+    /// cache is cleared afterwards when writing to the current process.
+    ///
+    /// # Safety
+    ///
+    /// The memory being overwritten must not be in use (e.g. executed) at the time of the write.
+    ///
+    /// [`SafeLocatorItem::append_code`]: crate::structs::SafeLocatorItem::append_code
+    pub unsafe fn write_bytes(&self, offset: usize, data: &[u8]) -> bool {
+        if offset > self.size || data.len() > self.size - offset {
+            return false;
+        }
+
+        let address = self.base_address.as_ptr() as usize + offset;
+        if self._this_process_id == get_sys_info().this_process_id {
+            let guard = DisableWriteXorExecuteGuard::new(address as *const u8, data.len());
+            copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len());
+            drop(guard);
+
+            clear_instruction_cache(address as *mut u8, (address + data.len()) as *mut u8);
+            return true;
+        }
+
+        #[cfg(target_os = "linux")]
+        return crate::utilities::linux_process_memory::write_process_memory(
+            self._this_process_id as i32,
+            address,
+            data,
+        )
+        .is_ok();
+
+        #[cfg(not(target_os = "linux"))]
+        false
+    }
+
     /// Returns an empty allocation, intended to be used as a non-result when an error is present.
     #[cfg(feature = "c_exports")]
     pub(crate) fn null() -> Self {
@@ -85,6 +172,7 @@ impl PrivateAllocation {
             Self {
                 base_address: NonNull::new_unchecked(null_mut()),
                 size: Default::default(),
+                page_size: Default::default(),
                 _this_process_id: Default::default(),
             }
         }
@@ -159,7 +247,18 @@ impl PrivateAllocation {
                     // Failed to free memory on Linux
                 }
             } else {
-                // Not Implemented
+                #[cfg(all(target_arch = "x86_64", feature = "external_processes"))]
+                {
+                    use crate::utilities::linux_remote_process::RemoteProcess;
+                    if let Ok(process) = RemoteProcess::attach(self._this_process_id as i32) {
+                        if process
+                            .munmap(self.base_address.as_ptr() as usize, self.size)
+                            .is_err()
+                        {
+                            // Failed to free memory on Linux in External Process
+                        }
+                    }
+                }
             };
         }
     }
@@ -202,6 +301,8 @@ impl Drop for PrivateAllocation {
 mod tests {
     use super::*;
     use crate::{internal::buffer_allocator, structs::params::BufferAllocatorSettings};
+    use core::mem::size_of;
+    use memoffset::offset_of;
 
     #[test]
     fn test_private_allocation() {
@@ -219,4 +320,49 @@ mod tests {
         assert_ne!(result.base_address().as_ptr() as usize, 0);
         assert!(result.size() >= 4096);
     }
+
+    #[test]
+    fn page_size_should_come_after_original_fields() {
+        // Returned by value through the C API; fields are only ever appended.
+        let word = size_of::<usize>();
+        assert_eq!(offset_of!(PrivateAllocation, base_address), 0);
+        assert_eq!(offset_of!(PrivateAllocation, size), word);
+        assert_eq!(offset_of!(PrivateAllocation, _this_process_id), word * 2);
+        assert_eq!(offset_of!(PrivateAllocation, page_size), word * 3);
+    }
+
+    #[test]
+    fn write_bytes_should_respect_bounds() {
+        let mut settings = BufferAllocatorSettings::new();
+        let alloc = buffer_allocator::allocate(&mut settings).unwrap();
+        let result = PrivateAllocation::new(
+            NonNull::<u8>::new(alloc.base_address.value as *mut u8).unwrap(),
+            alloc.size as usize,
+            get_sys_info().this_process_id,
+        );
+
+        unsafe {
+            assert!(result.write_bytes(1, &[1, 2, 3]));
+            assert_eq!(*result.base_address().as_ptr().add(2), 2);
+            assert!(result.write_bytes(result.size() - 1, &[4]));
+            assert!(!result.write_bytes(result.size() - 1, &[4, 5]));
+            assert!(!result.write_bytes(usize::MAX, &[]));
+        }
+    }
+
+    #[test]
+    fn can_reach_should_use_offset() {
+        let result = PrivateAllocation::new(
+            NonNull::<u8>::new(0x1000_0000 as *mut u8).unwrap(),
+            0x1000,
+            get_sys_info().this_process_id,
+        );
+
+        let kind = BranchKind::Arm64LdrLiteral;
+        assert!(result.can_reach(0, kind, 0x1000_0000 - (1 << 20)));
+        assert!(!result.can_reach(4, kind, 0x1000_0000 - (1 << 20)));
+        assert!(result.can_reach(4, kind, 0x1000_0000 + (1 << 20)));
+        assert!(!result.can_reach(0x1000, kind, 0x1000_0000));
+        core::mem::forget(result);
+    }
 }
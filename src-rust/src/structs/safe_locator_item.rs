@@ -1,5 +1,12 @@
 use crate::structs::internal::LocatorItem;
+use crate::structs::params::BranchKind;
+use crate::structs::BufferRegion;
 use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem::align_of;
+use core::ptr::NonNull;
+use core::slice::from_raw_parts;
+use core::str::from_utf8_unchecked;
 
 /// An individual item in the buffer locator that can be dropped (disposed).
 ///
@@ -7,12 +14,26 @@ use core::cell::Cell;
 ///
 /// Use at your own risk.
 /// Unsafe.
+///
+/// The lifetime is that of the [`BufferManager`] the item was taken from, as a private manager frees
+/// its buffers when dropped; items from the process wide manager are `'static`.
+///
+/// [`BufferManager`]: crate::buffer_manager::BufferManager
 #[repr(C)]
-pub struct SafeLocatorItem {
+pub struct SafeLocatorItem<'a> {
     pub item: Cell<*mut LocatorItem>,
+    _manager: PhantomData<&'a ()>,
 }
 
-impl SafeLocatorItem {
+impl<'a> SafeLocatorItem<'a> {
+    /// Wraps a locked item.
+    pub(crate) fn new(item: *mut LocatorItem) -> Self {
+        Self {
+            item: Cell::new(item),
+            _manager: PhantomData,
+        }
+    }
+
     /// Appends the code to this buffer.
     /// This is same as [`append_bytes`] but automatically clears the instruction cache on given CPU.
     ///
@@ -37,6 +58,39 @@ impl SafeLocatorItem {
         (*self.item.get()).append_code(data)
     }
 
+    /// Appends code which depends on its own address to this buffer, e.g. RIP-relative operands
+    /// or relative branches.
+    ///
+    /// # Arguments
+    ///
+    /// * `emit` - Called with the address the code will be written to, and the remaining space in the
+    ///   buffer to write it in. Returns the number of bytes written.
+    ///
+    /// # Returns
+    ///
+    /// The address of the written data.
+    ///
+    /// # Remarks
+    ///
+    /// Same as [`append_code`], W^X is toggled and the instruction cache is cleared only once for the
+    /// whole emitted code.
+    ///
+    /// # Panics
+    ///
+    /// If `emit` returns a length larger than the space it was given.
+    ///
+    /// # Safety
+    ///
+    /// The remaining space in the buffer must not be in use (e.g. executed) by anyone else.
+    ///
+    /// [`append_code`]: SafeLocatorItem::append_code
+    pub unsafe fn append_code_with<F>(&self, emit: F) -> usize
+    where
+        F: FnOnce(usize, &mut [u8]) -> usize,
+    {
+        (*self.item.get()).append_code_with(emit)
+    }
+
     /// Appends the data to this buffer.
     ///
     /// It is the caller's responsibility to ensure there is sufficient space in the buffer.
@@ -69,12 +123,92 @@ impl SafeLocatorItem {
     where
         T: Copy,
     {
-        (*self.item.get()).append_copy(data)
+        (*self.item.get()).append_copy(*data)
+    }
+
+    /// Appends the code to this buffer, returning the region it was written to.
+    /// Same as [`append_code`], but the region can be used to call or patch the code later.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`append_code`].
+    ///
+    /// [`append_code`]: SafeLocatorItem::append_code
+    pub unsafe fn append_code_region(&self, data: &[u8]) -> BufferRegion {
+        BufferRegion::new(self.append_code(data), data.len())
+    }
+
+    /// Appends the value to this buffer, aligned for `T`.
+    ///
+    /// # Returns
+    ///
+    /// Pointer to the written value. It stays valid for as long as the manager the buffer was
+    /// taken from.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have space for the value, plus up to `align_of::<T>() - 1` bytes of padding.
+    pub unsafe fn append_value<T: Copy>(&self, value: T) -> NonNull<T> {
+        let item = &mut *self.item.get();
+        item.align_position(align_of::<T>());
+        NonNull::new_unchecked(item.append_copy(value) as *mut T)
+    }
+
+    /// Appends the items to this buffer, aligned for `T`.
+    ///
+    /// # Returns
+    ///
+    /// The written items. They stay valid for as long as the manager the buffer was taken from.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have space for the items, plus up to `align_of::<T>() - 1` bytes of padding.
+    /// The returned slice must not be used after the memory is overwritten.
+    pub unsafe fn append_slice<T: Copy>(&self, data: &[T]) -> &'a [T] {
+        let item = &mut *self.item.get();
+        item.align_position(align_of::<T>());
+
+        from_raw_parts(item.append_slice(data) as *const T, data.len())
+    }
+
+    /// Appends the string to this buffer.
+    ///
+    /// # Returns
+    ///
+    /// The written string. It stays valid for as long as the manager the buffer was taken from.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have space for the string. The returned string must not be used after
+    /// the memory is overwritten.
+    pub unsafe fn append_str(&self, value: &str) -> &'a str {
+        from_utf8_unchecked(self.append_slice(value.as_bytes()))
+    }
+
+    /// Returns true if an instruction of the given kind, written at the current position of this
+    /// buffer, can reach `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of branch or reference to be written.
+    /// * `target` - Address the instruction should reference.
+    ///
+    /// # Remarks
+    ///
+    /// Check this before appending code to a buffer which was not obtained with
+    /// [`BufferSearchSettings::from_branch`], or which may have been written to since.
+    ///
+    /// [`BufferSearchSettings::from_branch`]: crate::structs::params::BufferSearchSettings::from_branch
+    pub fn can_reach(&self, kind: BranchKind, target: usize) -> bool {
+        unsafe {
+            let item = &*self.item.get();
+            kind.can_reach(item.min_address() + item.position as usize, target)
+        }
     }
 }
 
 /// Safely dispose.
-impl Drop for SafeLocatorItem {
+impl Drop for SafeLocatorItem<'_> {
     fn drop(&mut self) {
         unsafe {
             // Need to amend C API if we ever need to do anything more here, since it forgets item.
@@ -4,17 +4,20 @@ use windows_sys::Win32::System::{
     Threading::GetCurrentProcessId,
 };
 
+use core::ptr::addr_of;
+
 static mut CACHED: Option<Cached> = None;
 
 pub fn get_sys_info() -> &'static Cached {
     // No thread safety needed here (we're running code with no side effects), so we omit lazy_static to save on library space.
     unsafe {
-        if CACHED.is_some() {
-            return CACHED.as_ref().unwrap_unchecked();
+        let cached = &*addr_of!(CACHED);
+        if let Some(cached) = cached {
+            return cached;
         }
 
         make_sys_info();
-        return CACHED.as_ref().unwrap_unchecked();
+        (*addr_of!(CACHED)).as_ref().unwrap_unchecked()
     }
 }
 
@@ -51,11 +54,16 @@ impl Cached {
             &mut page_size,
         );
 
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        if let Some(detected) = Self::get_max_address_linux(page_size as usize) {
+            max_address = detected;
+        }
+
         Cached {
             max_address,
             allocation_granularity,
             this_process_id: Self::get_process_id(),
-            page_size: 4096,
+            page_size: page_size as u32,
         }
     }
 
@@ -87,6 +95,60 @@ impl Cached {
         }
     }
 
+    /// Finds the highest user space address by probing which addresses memory can be mapped at.
+    ///
+    /// # Remarks
+    ///
+    /// The limit (`TASK_SIZE` in the kernel) depends on the architecture and kernel configuration,
+    /// e.g. 47 bits on x86_64, 56 bits with 5-level paging; 39, 42, 47 or 48 bits on aarch64.
+    /// It isn't exposed directly; but mapping memory past it fails with `ENOMEM`.
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    fn get_max_address_linux(page_size: usize) -> Option<usize> {
+        // Possible virtual address sizes, largest first.
+        const ADDRESS_BITS: [u32; 7] = [57, 56, 52, 48, 47, 42, 39];
+
+        let bits = ADDRESS_BITS
+            .iter()
+            .find(|&&bits| Self::can_map_page_linux((1 << bits) - 2 * page_size, page_size))?;
+
+        // x86_64 reserves the last page below the limit, aarch64 doesn't.
+        let limit: usize = 1 << bits;
+        if Self::can_map_page_linux(limit - page_size, page_size) {
+            return Some(limit - 1);
+        }
+
+        Some(limit - page_size - 1)
+    }
+
+    /// Returns true if the page at the given address is in user space, i.e. it is either mapped,
+    /// or memory can be mapped there.
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    fn can_map_page_linux(address: usize, page_size: usize) -> bool {
+        use libc::{
+            mmap, munmap, EEXIST, MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED_NOREPLACE, MAP_PRIVATE,
+            PROT_NONE,
+        };
+
+        unsafe {
+            let result = mmap(
+                address as *mut _,
+                page_size,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            );
+
+            if result == MAP_FAILED {
+                return errno::errno().0 == EEXIST;
+            }
+
+            // Kernels before 4.17 treat the address as a hint; and place the memory elsewhere if invalid.
+            munmap(result, page_size);
+            result as usize == address
+        }
+    }
+
     #[allow(overflowing_literals)]
     #[cfg(not(target_os = "windows"))]
     fn get_address_and_allocation_granularity_mmap_rs(
@@ -102,7 +164,7 @@ impl Cached {
         if cfg!(target_pointer_width = "32") {
             *max_address = 0xFFFF_FFFF;
         } else if cfg!(target_pointer_width = "64") {
-            *max_address = 0x7FFFFFFFFFFF; // no max-address API, so restricted to Linux level
+            *max_address = 0x7FFFFFFFFFFF; // no max-address API, so restricted to Linux level (refined at runtime on Linux)
         }
 
         #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
@@ -119,3 +181,21 @@ impl Cached {
         *allocation_granularity = max(MmapOptions::allocation_granularity() as i32, *page_size);
     }
 }
+
+#[cfg(test)]
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_address_should_be_last_mappable_address() {
+        let info = get_sys_info();
+        let page_size = info.page_size as usize;
+        assert!(info.max_address >= (1 << 39) - 1);
+        assert!(Cached::can_map_page_linux(
+            info.max_address + 1 - page_size,
+            page_size
+        ));
+        assert!(!Cached::can_map_page_linux(info.max_address + 1, page_size));
+    }
+}
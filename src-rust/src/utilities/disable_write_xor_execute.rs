@@ -66,3 +66,35 @@ pub(crate) fn restore_write_xor_execute(address: *const u8, size: usize) {
         );
     }
 }
+
+/// Disables write XOR execute protection for a memory region until dropped.
+///
+/// # Remarks
+///
+/// Use this where code runs while protection is disabled which may panic (e.g. user callbacks),
+/// so protection is restored while unwinding.
+pub(crate) struct DisableWriteXorExecuteGuard {
+    address: *const u8,
+    size: usize,
+}
+
+impl DisableWriteXorExecuteGuard {
+    /// Disables write XOR execute protection; see [`disable_write_xor_execute`].
+    ///
+    /// # Parameters
+    ///
+    /// - `address`: The address of the memory to disable write XOR execute protection for.
+    /// - `size`: The size of the memory to disable write XOR execute protection for.
+    #[inline(always)]
+    pub(crate) fn new(address: *const u8, size: usize) -> Self {
+        disable_write_xor_execute(address, size);
+        Self { address, size }
+    }
+}
+
+impl Drop for DisableWriteXorExecuteGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        restore_write_xor_execute(self.address, self.size);
+    }
+}
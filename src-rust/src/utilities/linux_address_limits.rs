@@ -0,0 +1,192 @@
+// Ranges of the address space the Linux kernel refuses to map memory in, even if nothing is mapped there.
+//
+// - Below `vm.mmap_min_addr`, to keep null pointer dereferences from reaching mapped memory.
+// - Inside the guard gap below the main stack (`[stack]`), which is kept free so the stack can grow.
+//   Its size is set with the `stack_guard_gap` kernel parameter, in pages.
+
+use crate::utilities::cached::get_sys_info;
+use crate::utilities::linux_map_parser::{find_line, ProcFile};
+use crate::utilities::map_parser_utilities::MemoryMapEntry;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sentinel for 'not yet read'.
+const UNINITIALIZED: usize = usize::MAX;
+
+/// Value of `vm.mmap_min_addr` assumed if it can't be read; the default on most distributions.
+const DEFAULT_MMAP_MIN_ADDR: usize = 65536;
+
+/// Size of the stack guard gap in pages, if not overridden with the `stack_guard_gap` kernel parameter.
+const DEFAULT_STACK_GUARD_GAP_PAGES: usize = 256;
+
+static MMAP_MIN_ADDR: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+static STACK_GUARD_GAP: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+
+/// Returns the lowest address memory can be mapped at, i.e. `vm.mmap_min_addr`.
+pub fn get_mmap_min_addr() -> usize {
+    let mut value = MMAP_MIN_ADDR.load(Ordering::Relaxed);
+    if value == UNINITIALIZED {
+        value = read_mmap_min_addr().unwrap_or(DEFAULT_MMAP_MIN_ADDR);
+        MMAP_MIN_ADDR.store(value, Ordering::Relaxed);
+    }
+
+    value
+}
+
+/// Reads `vm.mmap_min_addr` from `/proc`.
+fn read_mmap_min_addr() -> Option<usize> {
+    let source = ProcFile::open("/proc/sys/vm/mmap_min_addr").ok()?;
+    find_line(source, |line| line.trim().parse().ok())
+}
+
+/// Returns the size of the gap kept free below the main stack, in bytes.
+pub fn get_stack_guard_gap() -> usize {
+    let mut value = STACK_GUARD_GAP.load(Ordering::Relaxed);
+    if value == UNINITIALIZED {
+        let page_size = get_sys_info().page_size as usize;
+        let source = ProcFile::open("/proc/cmdline").ok();
+        value = source
+            .and_then(|x| find_line(x, |line| Some(parse_stack_guard_gap(line, page_size))))
+            .unwrap_or(DEFAULT_STACK_GUARD_GAP_PAGES * page_size);
+        STACK_GUARD_GAP.store(value, Ordering::Relaxed);
+    }
+
+    value
+}
+
+/// Removes the ranges the kernel refuses to map memory in from a free region.
+///
+/// # Arguments
+///
+/// * `region` - Free region, with an inclusive end address.
+/// * `stack_start` - Start address of the `[stack]` mapping, if the process has one.
+///
+/// # Returns
+///
+/// What is left of the region, or `None` if the kernel refuses to map memory anywhere in it.
+pub fn exclude_refused_ranges(
+    region: MemoryMapEntry,
+    stack_start: Option<usize>,
+) -> Option<MemoryMapEntry> {
+    exclude_ranges(
+        region,
+        get_mmap_min_addr(),
+        stack_start,
+        get_stack_guard_gap(),
+    )
+}
+
+fn exclude_ranges(
+    mut region: MemoryMapEntry,
+    mmap_min_addr: usize,
+    stack_start: Option<usize>,
+    stack_guard_gap: usize,
+) -> Option<MemoryMapEntry> {
+    region.start_address = region.start_address.max(mmap_min_addr);
+
+    if let Some(stack_start) = stack_start {
+        let gap_start = stack_start.saturating_sub(stack_guard_gap);
+        if region.start_address < stack_start && region.end_address >= gap_start {
+            if gap_start == 0 {
+                return None;
+            }
+
+            region.end_address = region.end_address.min(gap_start - 1);
+        }
+    }
+
+    (region.start_address <= region.end_address).then_some(region)
+}
+
+/// Parses the stack guard gap, in bytes, from the kernel command line (`/proc/cmdline`).
+fn parse_stack_guard_gap(cmdline: &str, page_size: usize) -> usize {
+    let pages = cmdline
+        .split_ascii_whitespace()
+        .take_while(|x| *x != "--") // arguments for init
+        .find_map(|x| x.strip_prefix("stack_guard_gap="))
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_STACK_GUARD_GAP_PAGES);
+
+    pages * page_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclude_all(
+        regions: Vec<MemoryMapEntry>,
+        mmap_min_addr: usize,
+        stack_start: Option<usize>,
+        stack_guard_gap: usize,
+    ) -> Vec<MemoryMapEntry> {
+        regions
+            .into_iter()
+            .filter_map(|x| exclude_ranges(x, mmap_min_addr, stack_start, stack_guard_gap))
+            .collect()
+    }
+
+    #[test]
+    fn exclude_ranges_should_clip_below_mmap_min_addr() {
+        let regions = vec![
+            MemoryMapEntry::new(0, 0xFFF),
+            MemoryMapEntry::new(0x2000, 0x1FFFF),
+            MemoryMapEntry::new(0x30000, 0x3FFFF),
+        ];
+
+        let regions = exclude_all(regions, 0x10000, None, 0);
+        assert_eq!(
+            regions,
+            vec![
+                MemoryMapEntry::new(0x10000, 0x1FFFF),
+                MemoryMapEntry::new(0x30000, 0x3FFFF),
+            ]
+        );
+    }
+
+    #[test]
+    fn exclude_ranges_should_clip_stack_guard_gap() {
+        let regions = vec![
+            MemoryMapEntry::new(0x10000, 0x7FFF_FFFF),
+            MemoryMapEntry::new(0x8000_0000, 0x8007_FFFF),
+            MemoryMapEntry::new(0x9000_0000, 0xFFFF_FFFF),
+        ];
+
+        // Stack at 0x80100000, with a 1MiB gap.
+        let regions = exclude_all(regions, 0x10000, Some(0x8010_0000), 0x10_0000);
+        assert_eq!(
+            regions,
+            vec![
+                MemoryMapEntry::new(0x10000, 0x7FFF_FFFF),
+                MemoryMapEntry::new(0x9000_0000, 0xFFFF_FFFF),
+            ]
+        );
+
+        let regions = vec![MemoryMapEntry::new(0x10000, 0x8FFF_FFFF)];
+        let regions = exclude_all(regions, 0x10000, Some(0x9000_0000), 0x10_0000);
+        assert_eq!(regions, vec![MemoryMapEntry::new(0x10000, 0x8FEF_FFFF)]);
+    }
+
+    #[test]
+    fn parse_stack_guard_gap_should_read_kernel_parameter() {
+        assert_eq!(parse_stack_guard_gap("quiet splash", 4096), 256 * 4096);
+        assert_eq!(
+            parse_stack_guard_gap("quiet stack_guard_gap=16 splash", 4096),
+            16 * 4096
+        );
+        assert_eq!(
+            parse_stack_guard_gap("quiet -- stack_guard_gap=16", 4096),
+            256 * 4096
+        );
+    }
+
+    #[test]
+    fn get_mmap_min_addr_should_read_sysctl() {
+        let expected = std::fs::read_to_string("/proc/sys/vm/mmap_min_addr")
+            .ok()
+            .map(|x| x.trim().parse::<usize>().unwrap());
+        assert_eq!(
+            get_mmap_min_addr(),
+            expected.unwrap_or(DEFAULT_MMAP_MIN_ADDR)
+        );
+    }
+}
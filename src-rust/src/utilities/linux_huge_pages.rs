@@ -0,0 +1,188 @@
+// Utilities for allocating memory backed by huge pages on Linux.
+//
+// Explicit huge pages (`MAP_HUGETLB`) are only available if the system administrator reserved a pool
+// of them; otherwise transparent huge pages are requested with `madvise(MADV_HUGEPAGE)`, which the
+// kernel may or may not honour. The page size actually obtained is read back from `/proc/self/smaps`.
+
+use crate::utilities::linux_map_parser::{find_line, ByteSource, LineReader, ProcFile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sentinel for 'huge page size not yet read'.
+const HUGE_PAGE_SIZE_UNINITIALIZED: usize = 0;
+
+/// Sentinel for 'huge pages not supported'.
+const HUGE_PAGE_SIZE_UNSUPPORTED: usize = 1;
+
+static HUGE_PAGE_SIZE: AtomicUsize = AtomicUsize::new(HUGE_PAGE_SIZE_UNINITIALIZED);
+
+/// Returns the default huge page size of the system, or `None` if huge pages are not supported.
+pub fn get_huge_page_size() -> Option<usize> {
+    let mut size = HUGE_PAGE_SIZE.load(Ordering::Relaxed);
+    if size == HUGE_PAGE_SIZE_UNINITIALIZED {
+        size = ProcFile::open("/proc/meminfo")
+            .ok()
+            .and_then(parse_huge_page_size)
+            .unwrap_or(HUGE_PAGE_SIZE_UNSUPPORTED);
+        HUGE_PAGE_SIZE.store(size, Ordering::Relaxed);
+    }
+
+    if size == HUGE_PAGE_SIZE_UNSUPPORTED {
+        return None;
+    }
+
+    Some(size)
+}
+
+/// Returns the size of the pages backing the mapping which contains `address`,
+/// or `None` if it cannot be determined.
+///
+/// # Remarks
+///
+/// Transparent huge pages are only reported once the memory has been touched.
+pub fn get_page_size_at(address: usize) -> Option<usize> {
+    let smaps = ProcFile::open("/proc/self/smaps").ok()?;
+    parse_page_size_at(smaps, address, get_huge_page_size())
+}
+
+/// Parses the `Hugepagesize` field from the contents of `/proc/meminfo`.
+fn parse_huge_page_size(meminfo: impl ByteSource) -> Option<usize> {
+    find_line(meminfo, |line| {
+        line.strip_prefix("Hugepagesize:").and_then(parse_kilobytes)
+    })
+}
+
+/// Parses the page size of the mapping containing `address` from the contents of `/proc/self/smaps`.
+fn parse_page_size_at(
+    smaps: impl ByteSource,
+    address: usize,
+    huge_page_size: Option<usize>,
+) -> Option<usize> {
+    let mut in_mapping = false;
+    let mut kernel_page_size = None;
+    let mut anon_huge_pages = 0;
+
+    let mut lines = LineReader::new(smaps);
+    while let Some(line) = lines.next_line() {
+        let Ok(line) = core::str::from_utf8(line.ok()?) else {
+            continue;
+        };
+
+        let mut parts = line.split_ascii_whitespace();
+        let first = match parts.next() {
+            Some(first) => first,
+            None => continue,
+        };
+
+        // Field of the current mapping.
+        if first.ends_with(':') {
+            if !in_mapping {
+                continue;
+            }
+
+            match first {
+                "KernelPageSize:" => kernel_page_size = parse_kilobytes(&line[first.len()..]),
+                "AnonHugePages:" => {
+                    anon_huge_pages = parse_kilobytes(&line[first.len()..]).unwrap_or(0)
+                }
+                _ => {}
+            }
+
+            continue;
+        }
+
+        // Header of next mapping.
+        if in_mapping {
+            break;
+        }
+
+        if let Some((start, end)) = first.split_once('-') {
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+            in_mapping = address >= start && address < end;
+        }
+    }
+
+    let kernel_page_size = kernel_page_size?;
+    match huge_page_size {
+        Some(huge_page_size) if anon_huge_pages > 0 && huge_page_size > kernel_page_size => {
+            Some(huge_page_size)
+        }
+        _ => Some(kernel_page_size),
+    }
+}
+
+/// Parses a value such as `2048 kB` into bytes.
+fn parse_kilobytes(value: &str) -> Option<usize> {
+    let mut parts = value.split_ascii_whitespace();
+    let number: usize = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some("kB") => Some(number * 1024),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMAPS: &str = "\
+7f0000000000-7f0000200000 rw-p 00000000 00:00 0
+Size:               2048 kB
+KernelPageSize:        4 kB
+AnonHugePages:      2048 kB
+7f0000200000-7f0000400000 rw-s 00000000 00:0f 1234                       /anon_hugepage (deleted)
+Size:               2048 kB
+KernelPageSize:     2048 kB
+AnonHugePages:         0 kB
+7f0000400000-7f0000401000 r-xp 00000000 08:01 5678                       /usr/lib/libc.so
+Size:                  4 kB
+KernelPageSize:        4 kB
+AnonHugePages:         0 kB
+VmFlags: rd ex mr mw me
+";
+
+    #[test]
+    fn parse_huge_page_size_should_read_meminfo() {
+        let meminfo =
+            "MemTotal:       16316412 kB\nHugePages_Total:       0\nHugepagesize:       2048 kB\n";
+        assert_eq!(
+            parse_huge_page_size(meminfo.as_bytes()),
+            Some(2 * 1024 * 1024)
+        );
+        assert_eq!(
+            parse_huge_page_size("MemTotal:       16316412 kB\n".as_bytes()),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_page_size_at_should_detect_transparent_huge_pages() {
+        let huge = Some(2 * 1024 * 1024);
+        assert_eq!(
+            parse_page_size_at(SMAPS.as_bytes(), 0x7f0000001000, huge),
+            huge
+        );
+    }
+
+    #[test]
+    fn parse_page_size_at_should_detect_explicit_huge_pages() {
+        let huge = Some(2 * 1024 * 1024);
+        assert_eq!(
+            parse_page_size_at(SMAPS.as_bytes(), 0x7f0000200000, huge),
+            huge
+        );
+    }
+
+    #[test]
+    fn parse_page_size_at_should_detect_regular_pages() {
+        let huge = Some(2 * 1024 * 1024);
+        assert_eq!(
+            parse_page_size_at(SMAPS.as_bytes(), 0x7f0000400000, huge),
+            Some(4096)
+        );
+        assert_eq!(
+            parse_page_size_at(SMAPS.as_bytes(), 0x7f0000401000, huge),
+            None
+        );
+    }
+}
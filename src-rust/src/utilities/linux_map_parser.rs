@@ -1,71 +1,280 @@
-extern crate alloc;
+use super::linux_address_limits::exclude_refused_ranges;
 use super::map_parser_utilities::{get_free_regions, MemoryMapEntry};
-use alloc::ffi::CString;
+use crate::utilities::stack_c_string::StackCString;
+use core::cell::Cell;
+use core::cmp::min;
+use errno::errno;
+use libc::c_char;
 use libc::c_void;
 use libc::close;
 use libc::open;
 use libc::read;
 use libc::O_RDONLY;
 
-#[cfg(not(feature = "std"))]
+#[cfg(feature = "alloc")]
+use crate::structs::{MemoryMapRegion, MemoryPermissions};
+
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
 
-#[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+/// Size of the buffer [`LineReader`] reads into.
+/// Long enough for any line we care about; a line includes a path of up to `PATH_MAX` (4096) bytes.
+const READ_BUFFER_SIZE: usize = 8192;
 
-/// Parses the contents of the /proc/{id}/maps file and returns a vector of memory mapping entries.
-///
-/// # Arguments
-///
-/// * `process_id` - The process id to get mapping ranges for.
-///
-/// # Returns
+/// Source of bytes for a [`LineReader`].
+pub trait ByteSource {
+    /// Reads into the buffer, returning the number of bytes read (0 at end of input) or the `errno`.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, i32>;
+}
+
+/// A file opened for reading, closed on drop.
+pub struct ProcFile {
+    file_descriptor: i32,
+}
+
+impl ProcFile {
+    /// Opens the file at the given path, e.g. `/proc/meminfo`.
+    pub fn open(path: &str) -> Result<Self, i32> {
+        let path = StackCString::new(&[path]).ok_or(libc::ENAMETOOLONG)?;
+        let file_descriptor = unsafe { open(path.as_ptr(), O_RDONLY) };
+        if file_descriptor < 0 {
+            return Err(errno().0);
+        }
+
+        Ok(Self { file_descriptor })
+    }
+
+    /// Opens the `/proc/{id}/maps` file of the given process.
+    pub fn open_maps(process_id: i32) -> Result<Self, i32> {
+        // Construct the path to the maps file for the given process ID, without allocating.
+        let mut path = [0u8; 32];
+        let mut buffer = itoa::Buffer::new();
+        let id = buffer.format(process_id).as_bytes();
+        let parts: [&[u8]; 3] = [b"/proc/", id, b"/maps\0"];
+
+        let mut length = 0;
+        for part in parts {
+            path[length..length + part.len()].copy_from_slice(part);
+            length += part.len();
+        }
+
+        let file_descriptor = unsafe { open(path.as_ptr() as *const c_char, O_RDONLY) };
+        if file_descriptor < 0 {
+            return Err(errno().0);
+        }
+
+        Ok(Self { file_descriptor })
+    }
+}
+
+impl ByteSource for ProcFile {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, i32> {
+        let bytes_read = unsafe {
+            read(
+                self.file_descriptor,
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len(),
+            )
+        };
+
+        if bytes_read < 0 {
+            return Err(errno().0);
+        }
+
+        Ok(bytes_read as usize)
+    }
+}
+
+impl Drop for ProcFile {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.file_descriptor);
+        }
+    }
+}
+
+impl ByteSource for &[u8] {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, i32> {
+        let length = min(buffer.len(), self.len());
+        buffer[..length].copy_from_slice(&self[..length]);
+        *self = &self[length..];
+        Ok(length)
+    }
+}
+
+/// Reads a file line by line, using a fixed size buffer.
 ///
-/// This function returns a result which is:
+/// # Remarks
 ///
-/// * a vector of memory mapping entries if the function succeeds,
-/// * an error if the function fails.
-fn parse_memory_map_from_process_id(process_id: i32) -> Vec<MemoryMapEntry> {
-    // Construct the path to the maps file for the given process ID.
-    // no std!!
-    let mut maps_path = String::from("/proc/");
-    let mut buffer = itoa::Buffer::new();
-    maps_path.push_str(buffer.format(process_id));
-    maps_path.push_str("/maps");
+/// Lines longer than the buffer are truncated; the rest of the line is skipped.
+/// Yields an error, then stops, if reading fails.
+pub struct LineReader<S: ByteSource> {
+    source: S,
+    buffer: [u8; READ_BUFFER_SIZE],
+    start: usize,
+    end: usize,
+    finished: bool,
 
-    // Read all the lines from the file into a single string.
-    let all_lines = unsafe { read_to_string(&maps_path) };
+    /// True if we're skipping the remainder of a line that didn't fit in the buffer.
+    skipping_line: bool,
+}
+
+impl<S: ByteSource> LineReader<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            buffer: [0; READ_BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            finished: false,
+            skipping_line: false,
+        }
+    }
+
+    /// Returns the next line, without the trailing newline.
+    pub fn next_line(&mut self) -> Option<Result<&[u8], i32>> {
+        loop {
+            if let Some(newline) = self.buffer[self.start..self.end]
+                .iter()
+                .position(|&x| x == b'\n')
+            {
+                let start = self.start;
+                self.start += newline + 1;
+                if self.skipping_line {
+                    self.skipping_line = false;
+                    continue;
+                }
+
+                return Some(Ok(&self.buffer[start..start + newline]));
+            }
+
+            // Last line, without a trailing newline.
+            if self.finished {
+                let start = self.start;
+                self.start = self.end;
+                if start == self.end || self.skipping_line {
+                    return None;
+                }
+
+                self.skipping_line = true;
+                return Some(Ok(&self.buffer[start..self.end]));
+            }
+
+            // Line doesn't fit in the buffer; return what we have.
+            if self.start == 0 && self.end == self.buffer.len() {
+                self.start = self.end;
+                let was_skipping = core::mem::replace(&mut self.skipping_line, true);
+                if !was_skipping {
+                    return Some(Ok(&self.buffer[..]));
+                }
+            }
+
+            if let Err(error) = self.fill_buffer() {
+                self.finished = true;
+                self.skipping_line = true;
+                self.start = self.end;
+                return Some(Err(error));
+            }
+        }
+    }
+
+    /// Moves leftover data to the start of the buffer, and fills the rest of it.
+    fn fill_buffer(&mut self) -> Result<(), i32> {
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        let bytes_read = self.source.read(&mut self.buffer[self.end..])?;
+        if bytes_read == 0 {
+            self.finished = true;
+        }
 
-    // Split the string into lines.
-    let lines: Vec<&str> = all_lines.split('\n').collect();
-    parse_memory_map_from_lines(lines)
+        self.end += bytes_read;
+        Ok(())
+    }
 }
 
-/// Parses the contents of the /proc/{id}/maps file and returns a vector of memory mapping entries.
+/// Returns the first value `f` returns for a line of the given source, e.g. a file in `/proc`.
 ///
-/// # Arguments
+/// # Remarks
 ///
-/// * `process_id` - The process id to get mapping ranges for.
-///
-/// # Returns
+/// Lines which aren't valid UTF-8 are skipped. Returns `None` if reading fails.
+pub(crate) fn find_line<S: ByteSource, T>(
+    source: S,
+    mut f: impl FnMut(&str) -> Option<T>,
+) -> Option<T> {
+    let mut reader = LineReader::new(source);
+    while let Some(line) = reader.next_line() {
+        let Ok(line) = core::str::from_utf8(line.ok()?) else {
+            continue;
+        };
+
+        if let Some(value) = f(line) {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Streams the entries of a `/proc/{id}/maps` file, using a fixed size buffer.
 ///
-/// This function returns a result which is:
+/// # Remarks
 ///
-/// * a vector of memory mapping entries if the function succeeds,
-/// * an error if the function fails.
-fn parse_memory_map_from_lines(lines: Vec<&str>) -> Vec<MemoryMapEntry> {
-    let mut entries = Vec::new();
+/// Yields an error, then stops, if reading fails. Lines which can't be parsed are skipped.
+/// Lines longer than the buffer are parsed from their truncated start, which holds the address range.
+pub struct MemoryMapReader<S: ByteSource> {
+    lines: LineReader<S>,
+
+    /// Start address of the `[stack]` mapping, once read.
+    stack_start: Option<usize>,
+}
 
-    for line in lines {
-        if let Some(entry) = parse_memory_map_entry(line) {
-            entries.push(entry)
+impl MemoryMapReader<ProcFile> {
+    /// Opens the memory map of the given process.
+    pub fn open(process_id: i32) -> Result<Self, i32> {
+        Ok(Self::new(ProcFile::open_maps(process_id)?))
+    }
+}
+
+impl<S: ByteSource> MemoryMapReader<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            lines: LineReader::new(source),
+            stack_start: None,
         }
     }
 
-    entries
+    /// Returns the start address of the main thread's stack (`[stack]`), if it was read so far.
+    pub fn stack_start(&self) -> Option<usize> {
+        self.stack_start
+    }
+}
+
+impl<S: ByteSource> Iterator for MemoryMapReader<S> {
+    type Item = Result<MemoryMapEntry, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next_line()? {
+                Ok(line) => line,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let Some(entry) = parse_memory_map_entry(line) else {
+                continue;
+            };
+
+            if line.trim_ascii_end().ends_with(b"[stack]") {
+                self.stack_start = Some(entry.start_address);
+            }
+
+            return Some(Ok(entry));
+        }
+    }
 }
 
-/// Parses a line from the /proc/self/maps file (or equivalent) and returns a memory mapping entry.
+/// Parses the address range at the start of a line from the /proc/self/maps file (or equivalent).
 ///
 /// # Arguments
 ///
@@ -73,89 +282,152 @@ fn parse_memory_map_from_lines(lines: Vec<&str>) -> Vec<MemoryMapEntry> {
 ///
 /// # Returns
 ///
-/// This function returns a result which is:
-///
-/// * a memory map entry if the function succeeds,
-/// * an error if the function fails.
-fn parse_memory_map_entry(line: &str) -> Option<MemoryMapEntry> {
-    let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+/// The memory map entry, or `None` if the line does not start with a valid address range.
+fn parse_memory_map_entry(line: &[u8]) -> Option<MemoryMapEntry> {
+    let (start_address, rest) = parse_hex(line)?;
+    let (end_address, rest) = parse_hex(rest.strip_prefix(b"-")?)?;
+    if !rest.is_empty() && !rest[0].is_ascii_whitespace() {
+        return None;
+    }
 
-    if let Some(address_range) = parts.first() {
-        let addresses: Vec<&str> = address_range.split('-').collect();
-        if addresses.len() == 2 {
-            let start_address = usize::from_str_radix(addresses[0], 16);
-            let end_address = usize::from_str_radix(addresses[1], 16);
+    Some(MemoryMapEntry::new(start_address, end_address))
+}
 
-            if start_address.is_err() || end_address.is_err() {
-                return None;
-            }
+/// Parses a hex number at the start of `text`, returning it and the remaining text.
+fn parse_hex(text: &[u8]) -> Option<(usize, &[u8])> {
+    let mut value: usize = 0;
+    let mut length = 0;
+    for &byte in text {
+        let digit = match byte {
+            b'0'..=b'9' => byte - b'0',
+            b'a'..=b'f' => byte - b'a' + 10,
+            b'A'..=b'F' => byte - b'A' + 10,
+            _ => break,
+        };
 
-            unsafe {
-                return Some(MemoryMapEntry::new(
-                    start_address.unwrap_unchecked(),
-                    end_address.unwrap_unchecked(),
-                ));
-            }
-        }
+        value = value.checked_mul(16)?.checked_add(digit as usize)?;
+        length += 1;
     }
 
-    None
+    if length == 0 {
+        return None;
+    }
+
+    Some((value, &text[length..]))
 }
 
-/// Returns all free regions based on the found regions.
+/// Parses a line from the /proc/self/maps file (or equivalent), keeping all of its fields.
 ///
 /// # Arguments
 ///
-/// * `process_id` - ID of the process to get regions for.
-pub fn get_free_regions_from_process_id(process_id: i32) -> Vec<MemoryMapEntry> {
-    let regions = parse_memory_map_from_process_id(process_id);
-    get_free_regions(&regions)
+/// * `line` - A line from a memory maps file.
+///
+/// # Returns
+///
+/// The parsed region, or `None` if the line is not valid.
+#[cfg(feature = "alloc")]
+pub(crate) fn parse_memory_map_region(line: &str) -> Option<MemoryMapRegion> {
+    let mut rest = line;
+    let mut next_field = || {
+        let trimmed = rest.trim_start();
+        let end = trimmed
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(trimmed.len());
+        rest = &trimmed[end..];
+        Some(&trimmed[..end]).filter(|field| !field.is_empty())
+    };
+
+    let (start_address, end_address) = parse_address_range(next_field()?)?;
+    let permissions = MemoryPermissions::parse(next_field()?)?;
+    let offset = u64::from_str_radix(next_field()?, 16).ok()?;
+    let (device_major, device_minor) = next_field()?.split_once(':')?;
+    let device_major = u32::from_str_radix(device_major, 16).ok()?;
+    let device_minor = u32::from_str_radix(device_minor, 16).ok()?;
+    let inode = next_field()?.parse().ok()?;
+
+    // Path is the remainder of the line, and may contain spaces.
+    let path = rest.trim();
+    Some(MemoryMapRegion {
+        start_address,
+        end_address,
+        permissions,
+        offset,
+        device_major,
+        device_minor,
+        inode,
+        path: (!path.is_empty()).then(|| String::from(path)),
+    })
 }
 
-unsafe fn read_to_string(path: &str) -> String {
-    const BUFFER_SIZE: usize = 131_072; // 128 KB
-    let c_path = CString::new(path).unwrap();
-    let fd = open(c_path.as_ptr(), O_RDONLY);
-    if fd < 0 {
-        panic!("Can't read map file! Your Linux system is weird.");
-    }
+/// Parses an address range in the format `start-end`, with both addresses in hex.
+#[cfg(feature = "alloc")]
+fn parse_address_range(range: &str) -> Option<(usize, usize)> {
+    let (start_address, end_address) = range.split_once('-')?;
+    Some((
+        usize::from_str_radix(start_address, 16).ok()?,
+        usize::from_str_radix(end_address, 16).ok()?,
+    ))
+}
 
-    let mut content = String::with_capacity(BUFFER_SIZE);
+/// Finds all free regions of a process, excluding ranges the kernel refuses to map memory in.
+///
+/// # Arguments
+///
+/// * `process_id` - ID of the process to get regions for.
+/// * `on_region` - Called with each free region, in order of address.
+///
+/// # Returns
+///
+/// The `errno` if the memory map could not be read.
+pub fn get_free_regions_from_process_id(
+    process_id: i32,
+    on_region: &mut dyn FnMut(MemoryMapEntry),
+) -> Result<(), i32> {
+    let mut error = None;
+    let mut reader = MemoryMapReader::open(process_id)?;
 
-    loop {
-        let current_len = content.len();
-        let remaining_capacity = content.capacity() - current_len;
-        content.reserve(BUFFER_SIZE);
+    // Updated as each entry is read, so the region below `[stack]` is clipped when it is found.
+    let stack_start = Cell::new(None);
+    let regions = core::iter::from_fn(|| match reader.next()? {
+        Ok(entry) => {
+            stack_start.set(reader.stack_start());
+            Some(entry)
+        }
+        Err(read_error) => {
+            error = Some(read_error);
+            None
+        }
+    });
 
-        let buffer_ptr = content.as_mut_vec().as_mut_ptr().add(current_len) as *mut c_void;
-        let bytes_read = read(fd, buffer_ptr, remaining_capacity);
-        if bytes_read < 0 {
-            // Error occurred
-            close(fd);
-            panic!("Map read error");
-        } else if bytes_read == 0 {
-            // End of file
-            break;
-        } else {
-            content
-                .as_mut_vec()
-                .set_len(current_len + bytes_read as usize);
+    get_free_regions(regions, |region| {
+        // Don't offer addresses the kernel will refuse anyway.
+        if let Some(region) = exclude_refused_ranges(region, stack_start.get()) {
+            on_region(region);
         }
-    }
+    });
 
-    close(fd);
-    content
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utilities::linux_address_limits::get_mmap_min_addr;
+
+    fn read_all(text: &str) -> Vec<MemoryMapEntry> {
+        MemoryMapReader::new(text.as_bytes())
+            .map(|x| x.unwrap())
+            .collect()
+    }
 
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn parse_memory_map_entry_valid_line() {
         let line = "7f9c89991000-7f9c89993000 r--p 00000000 08:01 3932177                    /path/to/file";
-        let result = parse_memory_map_entry(line).unwrap();
+        let result = parse_memory_map_entry(line.as_bytes()).unwrap();
         assert_eq!(result.start_address, 0x7f9c89991000);
         assert_eq!(result.end_address, 0x7f9c89993000);
     }
@@ -164,7 +436,7 @@ mod tests {
     #[test]
     fn parse_memory_map_entry_valid_line_32() {
         let line = "9c89900-9c89c00 r--p 00000000 08:01 3932177                    /path/to/file";
-        let result = parse_memory_map_entry(line).unwrap();
+        let result = parse_memory_map_entry(line.as_bytes()).unwrap();
         assert_eq!(result.start_address, 0x9c89900);
         assert_eq!(result.end_address, 0x9c89c00);
     }
@@ -173,17 +445,17 @@ mod tests {
     #[should_panic]
     fn parse_memory_map_entry_invalid_line() {
         let line = "Invalid line";
-        let _ = parse_memory_map_entry(line).unwrap();
+        let _ = parse_memory_map_entry(line.as_bytes()).unwrap();
     }
 
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn parse_memory_map_valid_lines() {
-        let lines = vec![
+        let lines = [
             "7f9c89991000-7f9c89993000 r--p 00000000 08:01 3932177                    /path/to/file",
             "7f9c89994000-7f9c89995000 r--p 00000000 08:01 3932178                    /path/to/file"
         ];
-        let result = parse_memory_map_from_lines(lines);
+        let result = read_all(&lines.join("\n"));
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].start_address, 0x7f9c89991000);
         assert_eq!(result[0].end_address, 0x7f9c89993000);
@@ -194,15 +466,107 @@ mod tests {
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn parse_memory_map_valid_lines_32() {
-        let lines = vec![
+        let lines = [
             "9c89900-9c89C00 r--p 00000000 08:01 3932177                    /path/to/file",
             "9c89C00-9c89E00 r--p 00000000 08:01 3932178                    /path/to/file",
         ];
-        let result = parse_memory_map_from_lines(lines);
+        let result = read_all(&lines.join("\n"));
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].start_address, 0x9c89900);
         assert_eq!(result[0].end_address, 0x9c89C00);
         assert_eq!(result[1].start_address, 0x9c89C00);
         assert_eq!(result[1].end_address, 0x9c89E00);
     }
+
+    #[test]
+    fn reader_should_handle_lines_across_buffer_refills() {
+        let mut text = String::new();
+        for x in 0..1000usize {
+            text.push_str(&format!(
+                "{:x}-{:x} r--p 00000000 08:01 3932177   /path/to/file\n",
+                x * 0x1000,
+                x * 0x1000 + 0x1000
+            ));
+        }
+
+        let result = read_all(&text);
+        assert_eq!(result.len(), 1000);
+        assert!(result
+            .iter()
+            .enumerate()
+            .all(|(x, entry)| entry.start_address == x * 0x1000));
+    }
+
+    #[test]
+    fn reader_should_skip_remainder_of_long_lines() {
+        let long_path = "a".repeat(READ_BUFFER_SIZE * 2);
+        let text = format!(
+            "1000-2000 r--p 00000000 08:01 1 /{}\n3000-4000 r--p 00000000 08:01 1 /file",
+            long_path
+        );
+
+        let result = read_all(&text);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].start_address, 0x1000);
+        assert_eq!(result[1].start_address, 0x3000);
+        assert_eq!(result[1].end_address, 0x4000);
+    }
+
+    #[test]
+    fn reader_should_skip_invalid_lines() {
+        let result = read_all("Invalid line\n\n1000-2000 r--p 00000000 08:01 1\n");
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].start_address, 0x1000);
+    }
+
+    #[test]
+    fn reader_should_return_read_errors() {
+        struct FailingSource;
+        impl ByteSource for FailingSource {
+            fn read(&mut self, _buffer: &mut [u8]) -> Result<usize, i32> {
+                Err(libc::EIO)
+            }
+        }
+
+        let mut reader = MemoryMapReader::new(FailingSource);
+        assert_eq!(reader.next().unwrap().err(), Some(libc::EIO));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn find_line_should_return_first_match() {
+        let text = "MemTotal: 1 kB\nHugepagesize: 2048 kB\nHugepagesize: 4 kB";
+        let result = find_line(text.as_bytes(), |line| {
+            line.strip_prefix("Hugepagesize:").map(|x| x.trim().len())
+        });
+        assert_eq!(result, Some("2048 kB".len()));
+        assert_eq!(find_line(text.as_bytes(), |_| None::<()>), None);
+    }
+
+    #[test]
+    fn get_free_regions_from_process_id_should_fail_for_missing_process() {
+        assert_eq!(
+            get_free_regions_from_process_id(i32::MAX, &mut |_| {}).err(),
+            Some(libc::ENOENT)
+        );
+    }
+
+    #[test]
+    fn get_free_regions_from_process_id_should_read_own_process() {
+        let pid = crate::utilities::cached::get_sys_info().this_process_id as i32;
+        let mut free_regions = Vec::new();
+        get_free_regions_from_process_id(pid, &mut |x| free_regions.push(x)).unwrap();
+        assert!(!free_regions.is_empty());
+        assert!(free_regions[0].start_address >= get_mmap_min_addr());
+    }
+
+    #[test]
+    fn reader_should_find_stack() {
+        let text = "1000-2000 r--p 00000000 08:01 1 /file\n\
+                    7ffd14407000-7ffd14428000 rw-p 00000000 00:00 0                          [stack]\n\
+                    7ffd14500000-7ffd14502000 r-xp 00000000 00:00 0                          [vdso]\n";
+        let mut reader = MemoryMapReader::new(text.as_bytes());
+        assert_eq!(reader.by_ref().count(), 3);
+        assert_eq!(reader.stack_start(), Some(0x7ffd14407000));
+    }
 }
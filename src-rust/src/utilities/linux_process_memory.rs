@@ -0,0 +1,99 @@
+// Utilities for reading and writing memory of other processes on Linux.
+
+use core::ffi::c_void;
+use errno::errno;
+use libc::{iovec, pid_t, process_vm_readv, process_vm_writev, EFAULT};
+
+/// Reads memory of another process.
+///
+/// # Arguments
+///
+/// * `pid` - Id of the process to read from.
+/// * `address` - Address to read from.
+/// * `buffer` - Buffer to read into; the whole buffer is filled.
+///
+/// # Returns
+///
+/// `Ok` if the whole buffer was read, else the `errno`.
+#[cfg_attr(
+    all(
+        any(feature = "all_private", not(feature = "alloc")),
+        not(feature = "external_processes")
+    ),
+    allow(dead_code)
+)]
+pub fn read_process_memory(pid: pid_t, address: usize, buffer: &mut [u8]) -> Result<(), i32> {
+    let local = iovec {
+        iov_base: buffer.as_mut_ptr() as *mut c_void,
+        iov_len: buffer.len(),
+    };
+    let remote = iovec {
+        iov_base: address as *mut c_void,
+        iov_len: buffer.len(),
+    };
+
+    let read = unsafe { process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+    match read {
+        -1 => Err(errno().0),
+        read if read as usize == buffer.len() => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Writes memory of another process.
+///
+/// # Arguments
+///
+/// * `pid` - Id of the process to write to.
+/// * `address` - Address to write to.
+/// * `data` - Data to write.
+///
+/// # Returns
+///
+/// `Ok` if all of the data was written, else the `errno`.
+///
+/// # Remarks
+///
+/// Like a regular write, this respects the page protection of the target memory.
+pub fn write_process_memory(pid: pid_t, address: usize, data: &[u8]) -> Result<(), i32> {
+    let local = iovec {
+        iov_base: data.as_ptr() as *mut c_void,
+        iov_len: data.len(),
+    };
+    let remote = iovec {
+        iov_base: address as *mut c_void,
+        iov_len: data.len(),
+    };
+
+    let written = unsafe { process_vm_writev(pid, &local, 1, &remote, 1, 0) };
+    match written {
+        -1 => Err(errno().0),
+        written if written as usize == data.len() => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::cached::get_sys_info;
+
+    #[test]
+    fn can_read_and_write_own_memory() {
+        let pid = get_sys_info().this_process_id as pid_t;
+        let mut target = [0u8; 4];
+        let address = target.as_mut_ptr() as usize;
+
+        write_process_memory(pid, address, &[1, 2, 3, 4]).unwrap();
+        let mut buffer = [0u8; 4];
+        read_process_memory(pid, address, &mut buffer).unwrap();
+        assert_eq!(buffer, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn read_fails_for_unmapped_memory() {
+        let pid = get_sys_info().this_process_id as pid_t;
+        let mut buffer = [0u8; 4];
+        assert!(read_process_memory(pid, 0, &mut buffer).is_err());
+    }
+}
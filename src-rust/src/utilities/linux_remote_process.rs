@@ -0,0 +1,385 @@
+// Utilities for mapping memory in other processes on Linux.
+//
+// Linux has no equivalent of `VirtualAllocEx`, so mapping memory is done by stopping the target with
+// `ptrace` and making it execute the syscall itself, using a `syscall` instruction that already exists
+// in its address space. Only x86_64 is supported for now.
+
+use crate::utilities::linux_map_parser::{find_line, ProcFile};
+use crate::utilities::linux_process_memory::read_process_memory;
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::mem::zeroed;
+use core::ptr::null_mut;
+use errno::errno;
+use libc::{
+    pid_t, ptrace, user_regs_struct, waitpid, SYS_madvise, SYS_mmap, SYS_mprotect, SYS_munmap,
+    __WALL, ENOEXEC, ESRCH, PTRACE_DETACH, PTRACE_EVENT_STOP, PTRACE_GETREGS, PTRACE_INTERRUPT,
+    PTRACE_SEIZE, PTRACE_SETREGS, PTRACE_SINGLESTEP, SIGTRAP, WIFSTOPPED, WSTOPSIG,
+};
+
+/// Encoding of the x86_64 `syscall` instruction.
+const SYSCALL_INSTRUCTION: [u8; 2] = [0x0F, 0x05];
+
+/// A process stopped with `ptrace`, which can be made to execute syscalls on our behalf.
+///
+/// The process is resumed, with its original state restored, when this is dropped.
+pub struct RemoteProcess {
+    pid: pid_t,
+    original_regs: user_regs_struct,
+    syscall_address: usize,
+
+    /// Signal which arrived while the process was stopped, delivered when it is resumed.
+    pending_signal: Cell<i32>,
+
+    /// True while the process is in a ptrace stop; it can only be detached from while stopped.
+    is_stopped: Cell<bool>,
+}
+
+impl RemoteProcess {
+    /// Attaches to and stops the given process.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - Id of the process to attach to.
+    ///
+    /// # Returns
+    ///
+    /// The attached process, or the `errno` if the process can't be traced (e.g. it does not
+    /// exist, or we lack permission) or has no usable `syscall` instruction.
+    pub fn attach(pid: pid_t) -> Result<Self, i32> {
+        unsafe {
+            // From here on, Drop detaches from the process.
+            let mut result = Self::seize(pid)?;
+            result.interrupt()?;
+            if ptrace(
+                PTRACE_GETREGS,
+                pid,
+                null_mut::<c_void>(),
+                &mut result.original_regs as *mut _ as *mut c_void,
+            ) != 0
+            {
+                return Err(errno().0);
+            }
+
+            result.syscall_address = result.find_syscall_instruction().ok_or(ENOEXEC)?;
+            Ok(result)
+        }
+    }
+
+    /// Seizes the process, without stopping it.
+    unsafe fn seize(pid: pid_t) -> Result<Self, i32> {
+        if ptrace(
+            PTRACE_SEIZE,
+            pid,
+            null_mut::<c_void>(),
+            null_mut::<c_void>(),
+        ) != 0
+        {
+            return Err(errno().0);
+        }
+
+        Ok(Self {
+            pid,
+            original_regs: zeroed(),
+            syscall_address: 0,
+            pending_signal: Cell::new(0),
+            is_stopped: Cell::new(false),
+        })
+    }
+
+    /// Stops the process, and waits until it enters a ptrace stop.
+    unsafe fn interrupt(&self) -> Result<(), i32> {
+        if ptrace(
+            PTRACE_INTERRUPT,
+            self.pid,
+            null_mut::<c_void>(),
+            null_mut::<c_void>(),
+        ) != 0
+        {
+            return Err(errno().0);
+        }
+
+        self.wait_for_stop()?;
+        Ok(())
+    }
+
+    /// Maps memory in the process. Parameters and result are the same as for `mmap`.
+    pub unsafe fn mmap(
+        &self,
+        addr: usize,
+        size: usize,
+        prot: i32,
+        flags: i32,
+    ) -> Result<usize, i32> {
+        self.syscall(
+            SYS_mmap,
+            [
+                addr,
+                size,
+                prot as usize,
+                flags as usize,
+                -1_isize as usize,
+                0,
+            ],
+        )
+    }
+
+    /// Unmaps memory in the process. Parameters and result are the same as for `munmap`.
+    pub unsafe fn munmap(&self, addr: usize, size: usize) -> Result<usize, i32> {
+        self.syscall(SYS_munmap, [addr, size, 0, 0, 0, 0])
+    }
+
+    /// Changes the protection of memory in the process. Parameters and result are the same as for `mprotect`.
+    pub unsafe fn mprotect(&self, addr: usize, size: usize, prot: i32) -> Result<usize, i32> {
+        self.syscall(SYS_mprotect, [addr, size, prot as usize, 0, 0, 0])
+    }
+
+    /// Gives advice about memory in the process. Parameters and result are the same as for `madvise`.
+    pub unsafe fn madvise(&self, addr: usize, size: usize, advice: i32) -> Result<usize, i32> {
+        self.syscall(SYS_madvise, [addr, size, advice as usize, 0, 0, 0])
+    }
+
+    /// Makes the process execute a syscall.
+    ///
+    /// # Returns
+    ///
+    /// The result of the syscall, or the `errno` on failure.
+    pub unsafe fn syscall(&self, number: i64, args: [usize; 6]) -> Result<usize, i32> {
+        let mut regs = self.original_regs;
+        regs.rax = number as u64;
+        regs.rdi = args[0] as u64;
+        regs.rsi = args[1] as u64;
+        regs.rdx = args[2] as u64;
+        regs.r10 = args[3] as u64;
+        regs.r8 = args[4] as u64;
+        regs.r9 = args[5] as u64;
+        regs.rip = self.syscall_address as u64;
+
+        // Not inside a syscall, so the kernel doesn't try to restart an interrupted one.
+        regs.orig_rax = u64::MAX;
+        self.set_regs(&regs)?;
+
+        loop {
+            if ptrace(
+                PTRACE_SINGLESTEP,
+                self.pid,
+                null_mut::<c_void>(),
+                null_mut::<c_void>(),
+            ) != 0
+            {
+                return Err(errno().0);
+            }
+
+            self.is_stopped.set(false);
+            let status = self.wait_for_stop()?;
+            if WSTOPSIG(status) == SIGTRAP && (status >> 16) == 0 {
+                break;
+            }
+        }
+
+        if ptrace(
+            PTRACE_GETREGS,
+            self.pid,
+            null_mut::<c_void>(),
+            &mut regs as *mut _ as *mut c_void,
+        ) != 0
+        {
+            return Err(errno().0);
+        }
+
+        self.set_regs(&self.original_regs)?;
+
+        // Errors are returned as -errno in the range -4095..-1.
+        let result = regs.rax as isize;
+        if (-4095..0).contains(&result) {
+            return Err(-result as i32);
+        }
+
+        Ok(result as usize)
+    }
+
+    unsafe fn set_regs(&self, regs: &user_regs_struct) -> Result<(), i32> {
+        if ptrace(
+            PTRACE_SETREGS,
+            self.pid,
+            null_mut::<c_void>(),
+            regs as *const _ as *mut c_void,
+        ) != 0
+        {
+            return Err(errno().0);
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the process enters a ptrace stop, returning the wait status.
+    unsafe fn wait_for_stop(&self) -> Result<i32, i32> {
+        let mut status = 0;
+        if waitpid(self.pid, &mut status, __WALL) != self.pid {
+            return Err(errno().0);
+        }
+
+        if !WIFSTOPPED(status) {
+            return Err(ESRCH);
+        }
+
+        // Signals other than our own stops are held back until we detach.
+        let signal = WSTOPSIG(status);
+        let is_event = (status >> 16) != 0;
+        if !is_event && signal != SIGTRAP {
+            self.pending_signal.set(signal);
+        }
+
+        // Sanity check, we never set up any other events.
+        debug_assert!(!is_event || (status >> 16) == PTRACE_EVENT_STOP);
+        self.is_stopped.set(true);
+        Ok(status)
+    }
+
+    /// Finds a `syscall` instruction we can point the process at.
+    ///
+    /// # Remarks
+    ///
+    /// If the process was stopped inside a syscall, the instruction right before the
+    /// instruction pointer is used. Otherwise one is searched for in the vDSO.
+    unsafe fn find_syscall_instruction(&self) -> Option<usize> {
+        let previous = (self.original_regs.rip as usize).wrapping_sub(SYSCALL_INSTRUCTION.len());
+        let mut buffer = [0u8; 2];
+        if read_process_memory(self.pid, previous, &mut buffer).is_ok()
+            && buffer == SYSCALL_INSTRUCTION
+        {
+            return Some(previous);
+        }
+
+        let (start, end) = self.find_vdso()?;
+        let mut vdso = vec![0u8; end - start];
+        read_process_memory(self.pid, start, &mut vdso).ok()?;
+        vdso.windows(SYSCALL_INSTRUCTION.len())
+            .position(|x| x == SYSCALL_INSTRUCTION)
+            .map(|offset| start + offset)
+    }
+
+    /// Returns the address range of the process' vDSO.
+    unsafe fn find_vdso(&self) -> Option<(usize, usize)> {
+        let maps = ProcFile::open_maps(self.pid).ok()?;
+        find_line(maps, |line| {
+            if !line.ends_with("[vdso]") {
+                return None;
+            }
+
+            let range = line.split_ascii_whitespace().next()?;
+            let (start, end) = range.split_once('-')?;
+            Some((
+                usize::from_str_radix(start, 16).ok()?,
+                usize::from_str_radix(end, 16).ok()?,
+            ))
+        })
+    }
+}
+
+impl Drop for RemoteProcess {
+    fn drop(&mut self) {
+        unsafe {
+            // e.g. attaching failed before the process stopped, or a syscall failed while it was
+            // running; detaching fails unless the process is stopped, leaving it traced.
+            if !self.is_stopped.get() && self.interrupt().is_err() {
+                return;
+            }
+
+            if self.syscall_address != 0 {
+                let _ = self.set_regs(&self.original_regs);
+            }
+
+            ptrace(
+                PTRACE_DETACH,
+                self.pid,
+                null_mut::<c_void>(),
+                self.pending_signal.get() as usize as *mut c_void,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::linux_process_memory::write_process_memory;
+    use libc::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+    use std::process::{Child, Command};
+
+    fn spawn_child() -> Child {
+        Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    #[test]
+    fn can_map_write_and_unmap_in_child() {
+        let mut child = spawn_child();
+        let pid = child.id() as pid_t;
+
+        unsafe {
+            let process = RemoteProcess::attach(pid).unwrap();
+            let size = 4096;
+            let addr = process
+                .mmap(0, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS)
+                .unwrap();
+            assert_ne!(addr, 0);
+
+            write_process_memory(pid, addr, &[1, 2, 3, 4]).unwrap();
+            let mut buffer = [0u8; 4];
+            read_process_memory(pid, addr, &mut buffer).unwrap();
+            assert_eq!(buffer, [1, 2, 3, 4]);
+
+            process.munmap(addr, size).unwrap();
+            assert!(read_process_memory(pid, addr, &mut buffer).is_err());
+        }
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn syscall_errors_are_returned() {
+        let mut child = spawn_child();
+        let process = RemoteProcess::attach(child.id() as pid_t).unwrap();
+
+        // Unaligned address.
+        let result = unsafe { process.munmap(1, 4096) };
+        assert_eq!(result, Err(libc::EINVAL));
+
+        drop(process);
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn attach_fails_for_missing_process() {
+        assert!(RemoteProcess::attach(i32::MAX).is_err());
+    }
+
+    fn get_tracer_pid(pid: pid_t) -> pid_t {
+        std::fs::read_to_string(format!("/proc/{pid}/status"))
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn drop_detaches_from_process_which_is_not_stopped() {
+        let mut child = spawn_child();
+        let pid = child.id() as pid_t;
+
+        // Same state as when attaching fails before the process stops.
+        let process = unsafe { RemoteProcess::seize(pid).unwrap() };
+        assert_ne!(get_tracer_pid(pid), 0);
+
+        drop(process);
+        assert_eq!(get_tracer_pid(pid), 0);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+// Debug diagnostics for the locks in the locator.
+// Only compiled with the `lock_diagnostics` feature in builds with debug assertions.
+//
+// Detects a thread trying to acquire a lock it already holds (which would otherwise spin forever),
+// and reports locks which are held for a long time, alongside the thread that holds them and the
+// place in code where they were acquired.
+
+extern crate std;
+
+use crate::utilities::lock_owner::{current_thread_id, owner_thread_id};
+use core::panic::Location;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashMap;
+use std::eprintln;
+use std::string::{String, ToString};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Environment variable which overrides the time (in milliseconds) after which
+/// a thread waiting for a lock reports it.
+const THRESHOLD_ENV_VAR: &str = "RELOADED_MEMORY_BUFFERS_LOCK_REPORT_MS";
+
+/// Default time after which a thread waiting for a lock reports it.
+const DEFAULT_THRESHOLD_MS: u64 = 5000;
+
+/// Sentinel for 'threshold not yet read from environment'.
+const THRESHOLD_UNINITIALIZED: u64 = u64::MAX;
+
+static THRESHOLD_MS: AtomicU64 = AtomicU64::new(THRESHOLD_UNINITIALIZED);
+
+/// Locations where currently held locks were acquired, keyed by address of lock.
+static ACQUIRED_AT: Mutex<Option<HashMap<usize, &'static Location<'static>>>> = Mutex::new(None);
+
+/// Records the location where the lock at `address` was acquired.
+pub(crate) fn on_acquired(address: usize, location: &'static Location<'static>) {
+    if let Ok(mut map) = ACQUIRED_AT.lock() {
+        map.get_or_insert_with(HashMap::new)
+            .insert(address, location);
+    }
+}
+
+/// Forgets the location where the lock at `address` was acquired.
+pub(crate) fn on_released(address: usize) {
+    if let Ok(mut map) = ACQUIRED_AT.lock() {
+        if let Some(map) = map.as_mut() {
+            map.remove(&address);
+        }
+    }
+}
+
+fn get_report_threshold() -> Duration {
+    let mut millis = THRESHOLD_MS.load(Ordering::Relaxed);
+    if millis == THRESHOLD_UNINITIALIZED {
+        millis = std::env::var(THRESHOLD_ENV_VAR)
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_THRESHOLD_MS);
+        THRESHOLD_MS.store(millis, Ordering::Relaxed);
+    }
+
+    Duration::from_millis(millis)
+}
+
+fn get_acquired_at(address: usize) -> Option<&'static Location<'static>> {
+    ACQUIRED_AT
+        .lock()
+        .ok()
+        .and_then(|map| map.as_ref().and_then(|map| map.get(&address).copied()))
+}
+
+/// Tracks a single attempt at acquiring a lock with [`LocatorHeader::lock`] or [`LocatorItem::lock`].
+///
+/// [`LocatorHeader::lock`]: crate::structs::internal::LocatorHeader::lock
+/// [`LocatorItem::lock`]: crate::structs::internal::LocatorItem::lock
+pub(crate) struct LockWait {
+    name: &'static str,
+    address: usize,
+    started: Instant,
+    threshold: Duration,
+    reported: bool,
+}
+
+impl LockWait {
+    /// Starts tracking a wait for a lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the structure that owns the lock.
+    /// * `address` - Address of the lock.
+    /// * `lock_value` - Current value of the lock.
+    ///
+    /// # Panics
+    ///
+    /// If the lock is already held by the current thread.
+    pub(crate) fn new(name: &'static str, address: usize, lock_value: i32) -> Self {
+        Self::with_threshold(name, address, lock_value, get_report_threshold())
+    }
+
+    /// Starts tracking a wait for a lock, reporting it after the given time
+    /// instead of the configured threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the structure that owns the lock.
+    /// * `address` - Address of the lock.
+    /// * `lock_value` - Current value of the lock.
+    /// * `threshold` - Time after which the wait is reported.
+    ///
+    /// # Panics
+    ///
+    /// If the lock is already held by the current thread.
+    fn with_threshold(
+        name: &'static str,
+        address: usize,
+        lock_value: i32,
+        threshold: Duration,
+    ) -> Self {
+        if let Some(owner) = owner_thread_id(lock_value) {
+            if Some(owner) == current_thread_id() {
+                match get_acquired_at(address) {
+                    Some(location) => panic!(
+                        "Attempted to re-lock {} at {:#X} on thread {}, which already holds it (acquired at {}).",
+                        name, address, owner, location
+                    ),
+                    None => panic!(
+                        "Attempted to re-lock {} at {:#X} on thread {}, which already holds it.",
+                        name, address, owner
+                    ),
+                }
+            }
+        }
+
+        Self {
+            name,
+            address,
+            started: Instant::now(),
+            threshold,
+            reported: false,
+        }
+    }
+
+    /// Called after each failed attempt to acquire the lock.
+    /// Reports the lock (once) if the wait exceeds the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `lock_value` - Current value of the lock.
+    pub(crate) fn on_spin(&mut self, lock_value: i32) {
+        if self.reported {
+            return;
+        }
+
+        let elapsed = self.started.elapsed();
+        if elapsed < self.threshold {
+            return;
+        }
+
+        self.reported = true;
+        let holder = match owner_thread_id(lock_value) {
+            Some(id) => id.to_string(),
+            None => String::from("unknown"),
+        };
+
+        match get_acquired_at(self.address) {
+            Some(location) => eprintln!(
+                "[reloaded-memory-buffers] Waited {}ms for {} lock at {:#X}, held by thread {} (acquired at {}).",
+                elapsed.as_millis(), self.name, self.address, holder, location
+            ),
+            None => eprintln!(
+                "[reloaded-memory-buffers] Waited {}ms for {} lock at {:#X}, held by thread {}.",
+                elapsed.as_millis(), self.name, self.address, holder
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_acquired_should_record_location() {
+        let address = 0x1234;
+        let location = Location::caller();
+
+        on_acquired(address, location);
+        assert_eq!(get_acquired_at(address), Some(location));
+
+        on_released(address);
+        assert_eq!(get_acquired_at(address), None);
+    }
+
+    #[test]
+    fn on_spin_should_report_once_after_threshold() {
+        let mut wait = LockWait::with_threshold("Test", 0x5678, 0, Duration::ZERO);
+
+        wait.on_spin(0);
+        assert!(wait.reported);
+    }
+}
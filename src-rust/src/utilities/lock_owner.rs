@@ -0,0 +1,219 @@
+// Utilities for storing the owning thread inside of the locks used by the locator.
+//
+// Locks in the locator are plain `u32` values, where `0` means 'unlocked'.
+// When this library takes a lock, it writes the ID of the current thread (tagged with
+// `OWNER_FLAG`) instead of `1`; which allows other threads to detect when the owner of a lock
+// has died (e.g. thread was killed or exited abnormally while holding a buffer) and take over
+// the lock.
+//
+// Any other non-zero value (e.g. `1`, as written by older versions and other implementations)
+// has no known owner, and is never recovered.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use {core::ffi::c_char, errno::errno, libc::access, libc::F_OK};
+
+#[cfg(target_os = "windows")]
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, GetLastError, ERROR_INVALID_PARAMETER},
+    System::Threading::{
+        GetCurrentThreadId, GetExitCodeThread, OpenThread, THREAD_QUERY_LIMITED_INFORMATION,
+    },
+};
+
+/// Set in a lock value when the remaining 31 bits hold the ID of the owning thread.
+const OWNER_FLAG: u32 = 0x8000_0000;
+
+/// Lock value used when the owner cannot be represented.
+/// This is also the value written by older versions of the library.
+const ANONYMOUS_OWNER: i32 = 1;
+
+/// Exit code returned by `GetExitCodeThread` for threads which are still running.
+#[cfg(target_os = "windows")]
+const STILL_ACTIVE: u32 = 259;
+
+/// Returns the value that should be written into a lock when it is acquired by the current thread.
+pub(crate) fn current_owner_value() -> i32 {
+    match current_thread_id() {
+        Some(id) if id != 0 && id & OWNER_FLAG == 0 => (id | OWNER_FLAG) as i32,
+        _ => ANONYMOUS_OWNER,
+    }
+}
+
+/// Extracts the ID of the thread which owns the lock from the lock's value.
+///
+/// # Returns
+///
+/// The thread ID, or `None` if the lock is not taken, or the owner is unknown.
+pub(crate) fn owner_thread_id(lock_value: i32) -> Option<u32> {
+    let value = lock_value as u32;
+    if value & OWNER_FLAG == 0 {
+        return None;
+    }
+
+    Some(value & !OWNER_FLAG)
+}
+
+/// Attempts to take over a lock which was observed holding `observed_value`,
+/// if the owning thread is known to no longer exist.
+///
+/// # Arguments
+///
+/// * `lock` - The lock to take over.
+/// * `observed_value` - Value of the lock as observed by a failed acquisition attempt.
+/// * `new_value` - Value to write into the lock if taken over. See [`current_owner_value`].
+///
+/// # Returns
+///
+/// `true` if the lock is now owned by the caller, `false` otherwise.
+///
+/// # Remarks
+///
+/// The data guarded by a recovered lock may have been left mid-modification by the dead owner.
+/// For locator items, this at worst means some bytes at the end of the buffer go unused.
+pub(crate) fn try_recover(lock: &AtomicI32, observed_value: i32, new_value: i32) -> bool {
+    let owner = match owner_thread_id(observed_value) {
+        Some(owner) => owner,
+        None => return false,
+    };
+
+    if is_thread_alive(owner) {
+        return false;
+    }
+
+    // Only succeeds if nobody else recovered (or released) the lock in the meantime.
+    lock.compare_exchange(
+        observed_value,
+        new_value,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    )
+    .is_ok()
+}
+
+/// Returns the ID of the current thread, if the platform supports it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn current_thread_id() -> Option<u32> {
+    Some(unsafe { libc::syscall(libc::SYS_gettid) } as u32)
+}
+
+/// Returns the ID of the current thread, if the platform supports it.
+#[cfg(target_os = "windows")]
+pub(crate) fn current_thread_id() -> Option<u32> {
+    Some(unsafe { GetCurrentThreadId() })
+}
+
+/// Returns the ID of the current thread, if the platform supports it.
+#[cfg(target_os = "macos")]
+pub(crate) fn current_thread_id() -> Option<u32> {
+    let mut id: u64 = 0;
+    let result = unsafe { libc::pthread_threadid_np(0, &mut id) };
+    if result != 0 {
+        return None;
+    }
+
+    Some(id as u32)
+}
+
+/// Returns the ID of the current thread, if the platform supports it.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "windows",
+    target_os = "macos"
+)))]
+pub(crate) fn current_thread_id() -> Option<u32> {
+    None
+}
+
+/// Checks whether a thread with the given ID still exists in the current process.
+///
+/// # Remarks
+///
+/// If the answer cannot be determined, the thread is assumed to be alive.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_thread_alive(thread_id: u32) -> bool {
+    const TASK_DIR: &[u8] = b"/proc/self/task/";
+
+    // '/proc/self/task/' + u32 + null terminator.
+    let mut path = [0u8; TASK_DIR.len() + 11];
+    path[..TASK_DIR.len()].copy_from_slice(TASK_DIR);
+
+    let mut buffer = itoa::Buffer::new();
+    let id = buffer.format(thread_id).as_bytes();
+    path[TASK_DIR.len()..TASK_DIR.len() + id.len()].copy_from_slice(id);
+
+    unsafe {
+        if access(path.as_ptr() as *const c_char, F_OK) == 0 || errno().0 != libc::ENOENT {
+            return true;
+        }
+
+        // Task entry is missing, make sure that's not because procfs is unavailable.
+        access(c"/proc/self/task".as_ptr(), F_OK) != 0
+    }
+}
+
+/// Checks whether a thread with the given ID still exists in the current process.
+///
+/// # Remarks
+///
+/// If the answer cannot be determined, the thread is assumed to be alive.
+#[cfg(target_os = "windows")]
+fn is_thread_alive(thread_id: u32) -> bool {
+    unsafe {
+        let handle = OpenThread(THREAD_QUERY_LIMITED_INFORMATION, 0, thread_id);
+        if handle == 0 {
+            // Invalid parameter is returned for IDs which don't belong to any thread.
+            return GetLastError() != ERROR_INVALID_PARAMETER;
+        }
+
+        let mut exit_code: u32 = 0;
+        let has_exit_code = GetExitCodeThread(handle, &mut exit_code) != 0;
+        CloseHandle(handle);
+        !has_exit_code || exit_code == STILL_ACTIVE
+    }
+}
+
+/// Checks whether a thread with the given ID still exists in the current process.
+///
+/// # Remarks
+///
+/// Not supported on this platform, threads are always assumed to be alive.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "windows")))]
+fn is_thread_alive(_thread_id: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_thread_id_should_round_trip_current_thread() {
+        let value = current_owner_value();
+
+        match current_thread_id() {
+            Some(id) if id & OWNER_FLAG == 0 => assert_eq!(owner_thread_id(value), Some(id)),
+            _ => assert_eq!(owner_thread_id(value), None),
+        }
+    }
+
+    #[test]
+    fn owner_thread_id_should_be_none_for_anonymous_locks() {
+        assert_eq!(owner_thread_id(0), None);
+        assert_eq!(owner_thread_id(ANONYMOUS_OWNER), None);
+    }
+
+    #[test]
+    fn try_recover_should_not_take_lock_from_live_thread() {
+        let lock = AtomicI32::new(current_owner_value());
+        assert!(!try_recover(&lock, lock.load(Ordering::Acquire), 0));
+    }
+
+    #[test]
+    fn try_recover_should_not_take_anonymous_lock() {
+        let lock = AtomicI32::new(ANONYMOUS_OWNER);
+        assert!(!try_recover(&lock, ANONYMOUS_OWNER, current_owner_value()));
+    }
+}
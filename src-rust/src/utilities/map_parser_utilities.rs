@@ -1,12 +1,38 @@
-extern crate alloc;
-
 use super::cached::get_sys_info;
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::vec::Vec;
 
+/// Max number of free regions in a [`FreeRegions`] list, without the `alloc` feature.
+#[cfg(not(feature = "alloc"))]
+pub const MAX_FREE_REGIONS: usize = 512;
+
+/// List of free regions of a process, sorted by address.
+#[cfg(feature = "alloc")]
+pub(crate) type FreeRegions = Vec<MemoryMapEntry>;
+
+/// List of free regions of a process, sorted by address.
+#[cfg(not(feature = "alloc"))]
+pub(crate) type FreeRegions = crate::structs::FixedVec<MemoryMapEntry, MAX_FREE_REGIONS>;
+
+/// Appends a free region to the list.
+///
+/// # Returns
+///
+/// `false` if the list is full, which only happens without the `alloc` feature.
+pub(crate) fn push_free_region(free_regions: &mut FreeRegions, region: MemoryMapEntry) -> bool {
+    #[cfg(feature = "alloc")]
+    {
+        free_regions.push(region);
+        true
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    free_regions.push(region)
+}
+
 // Generic structure to use for custom parsers.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct MemoryMapEntry {
     pub start_address: usize,
     pub end_address: usize,
@@ -39,20 +65,31 @@ impl MemoryMapEntryTrait for MemoryMapEntry {
     }
 }
 
-/// Returns all free regions based on the found regions.
+impl<T: MemoryMapEntryTrait> MemoryMapEntryTrait for &T {
+    fn start_address(&self) -> usize {
+        (*self).start_address()
+    }
+
+    fn end_address(&self) -> usize {
+        (*self).end_address()
+    }
+}
+
+/// Finds all free regions based on the found regions.
 ///
 /// # Arguments
 ///
-/// * `regions` - A slice of MemoryMapEntry that contains the regions.
-
+/// * `regions` - The mapped regions, sorted by address. May be streamed straight from a parser.
+/// * `on_region` - Called with each free region, in order of address.
 #[cfg_attr(feature = "size_opt", optimize(size))]
-pub fn get_free_regions<T: MemoryMapEntryTrait>(regions: &[T]) -> Vec<MemoryMapEntry> {
+pub fn get_free_regions<T: MemoryMapEntryTrait>(
+    regions: impl IntoIterator<Item = T>,
+    mut on_region: impl FnMut(MemoryMapEntry),
+) {
     let mut last_end_address: usize = 0;
-    let mut free_regions = Vec::with_capacity(regions.len() + 2); // +2 for start and finish
-
-    for entry in regions.iter() {
+    for entry in regions {
         if entry.start_address() > last_end_address {
-            free_regions.push(MemoryMapEntry {
+            on_region(MemoryMapEntry {
                 start_address: last_end_address,
                 end_address: entry.start_address() - 1,
             });
@@ -63,19 +100,23 @@ pub fn get_free_regions<T: MemoryMapEntryTrait>(regions: &[T]) -> Vec<MemoryMapE
 
     // After the last region, up to the end of memory
     if last_end_address < get_sys_info().max_address {
-        free_regions.push(MemoryMapEntry {
+        on_region(MemoryMapEntry {
             start_address: last_end_address,
             end_address: get_sys_info().max_address,
         });
     }
-
-    free_regions
 }
 
 #[cfg(test)]
 mod tests {
     use crate::utilities::map_parser_utilities::{get_free_regions, MemoryMapEntry};
 
+    fn collect_free_regions(regions: &[MemoryMapEntry]) -> Vec<MemoryMapEntry> {
+        let mut free_regions = Vec::new();
+        get_free_regions(regions, |x| free_regions.push(x));
+        free_regions
+    }
+
     #[test]
     fn get_free_regions_with_no_gap() {
         let regions = vec![
@@ -83,7 +124,7 @@ mod tests {
             MemoryMapEntry::new(10, 20),
             MemoryMapEntry::new(20, usize::MAX),
         ];
-        let free_regions = get_free_regions(&regions);
+        let free_regions = collect_free_regions(&regions);
         assert_eq!(free_regions.len(), 0);
     }
 
@@ -94,7 +135,7 @@ mod tests {
             MemoryMapEntry::new(10, 20),
             MemoryMapEntry::new(30, usize::MAX),
         ];
-        let free_regions = get_free_regions(&regions);
+        let free_regions = collect_free_regions(&regions);
         assert_eq!(free_regions.len(), 1);
         assert_eq!(free_regions[0].start_address, 20);
         assert_eq!(free_regions[0].end_address, 29);
@@ -107,7 +148,7 @@ mod tests {
             MemoryMapEntry::new(20, 30),
             MemoryMapEntry::new(40, usize::MAX),
         ];
-        let free_regions = get_free_regions(&regions);
+        let free_regions = collect_free_regions(&regions);
         assert_eq!(free_regions.len(), 2);
         assert_eq!(free_regions[0].start_address, 10);
         assert_eq!(free_regions[0].end_address, 19);
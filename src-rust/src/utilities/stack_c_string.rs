@@ -0,0 +1,84 @@
+use core::ffi::c_char;
+
+/// Capacity of a [`StackCString`], including the null terminator; `PATH_MAX` on Linux.
+const CAPACITY: usize = 4096;
+
+/// A null terminated string (e.g. a path passed to the OS), built on the stack without allocating.
+pub struct StackCString {
+    buffer: [u8; CAPACITY],
+    length: usize,
+}
+
+impl StackCString {
+    /// Creates a string by concatenating the given parts.
+    ///
+    /// # Returns
+    ///
+    /// The string, or `None` if it is too long or a part contains a null character.
+    pub fn new(parts: &[&str]) -> Option<Self> {
+        let mut result = Self {
+            buffer: [0; CAPACITY],
+            length: 0,
+        };
+
+        for part in parts {
+            if !result.push_str(part) {
+                return None;
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Appends text to the string.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the text does not fit or contains a null character, in which case the string
+    /// is left unchanged.
+    pub fn push_str(&mut self, text: &str) -> bool {
+        let end = self.length + text.len();
+        if end >= CAPACITY || text.as_bytes().contains(&0) {
+            return false;
+        }
+
+        self.buffer[self.length..end].copy_from_slice(text.as_bytes());
+        self.buffer[end] = 0;
+        self.length = end;
+        true
+    }
+
+    /// Returns the string, without the null terminator.
+    #[cfg_attr(feature = "all_private", allow(dead_code))]
+    pub fn as_str(&self) -> &str {
+        // Only ever built from `&str`s.
+        unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.length]) }
+    }
+
+    /// Returns a pointer to the null terminated string.
+    pub fn as_ptr(&self) -> *const c_char {
+        self.buffer.as_ptr() as *const c_char
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ffi::CStr;
+
+    #[test]
+    fn new_should_concatenate_and_terminate() {
+        let text = StackCString::new(&["/proc/", "1234", "/maps"]).unwrap();
+        assert_eq!(text.as_str(), "/proc/1234/maps");
+
+        let c_str = unsafe { CStr::from_ptr(text.as_ptr()) };
+        assert_eq!(c_str.to_bytes(), b"/proc/1234/maps");
+    }
+
+    #[test]
+    fn new_should_reject_invalid_strings() {
+        assert!(StackCString::new(&["a\0b"]).is_none());
+        assert!(StackCString::new(&[&"a".repeat(CAPACITY)]).is_none());
+        assert!(StackCString::new(&[&"a".repeat(CAPACITY - 1)]).is_some());
+    }
+}
@@ -55,7 +55,7 @@ internal unsafe struct LocatorHeader
     /// <summary>
     ///     Returns true if the current item is locked, else false.
     /// </summary>
-    public bool IsLocked => _isLocked == 1;
+    public bool IsLocked => _isLocked != 0;
 
     private byte _flags;
     private readonly byte _pad1;
@@ -27,7 +27,7 @@ internal struct LocatorItem
     /// <summary>
     ///     Returns true if the current item is locked, else false.
     /// </summary>
-    public bool IsTaken => _isTaken == 1;
+    public bool IsTaken => _isTaken != 0;
 
     /// <summary>
     ///     Size of the buffer.